@@ -0,0 +1,171 @@
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    tiers::{default_density, default_restitution, BallTiers},
+    SPECIAL_BALL_SHIMMER_RATE,
+};
+
+#[derive(Component)]
+pub struct Ball {
+    pub ball_type: BallType,
+}
+
+#[derive(Component)]
+pub struct BallProgress(pub f32);
+
+#[derive(Component)]
+pub struct BallTarget(pub i32);
+
+/// Flicks a wildcard ball through every ball colour so it stands out from the rest.
+#[derive(Component)]
+pub struct Shimmer;
+
+/// Burn progress of a bomb that has landed, from 0 until it goes off at 1.
+#[derive(Component)]
+pub struct Fuse(pub f32);
+
+/// Flashes a lit bomb, faster and faster as its fuse burns down.
+pub fn fuse_system(
+    tiers: Res<BallTiers>,
+    fuses: Query<(&Fuse, &Handle<ColorMaterial>)>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (fuse, material) in fuses.iter() {
+        let lit = (fuse.0 * fuse.0 * 12.).fract() < 0.5;
+        let color = if lit {
+            Color::ORANGE_RED
+        } else {
+            BallType::Bomb.color(&tiers).color
+        };
+        materials.insert(material, ColorMaterial::from(color));
+    }
+}
+
+pub fn shimmer_system(
+    time: Res<Time>,
+    tiers: Res<BallTiers>,
+    shimmering: Query<&Handle<ColorMaterial>, With<Shimmer>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let level =
+        (time.elapsed_seconds() * SPECIAL_BALL_SHIMMER_RATE) as i32 % tiers.tiers.len() as i32 + 1;
+    for material in shimmering.iter() {
+        materials.insert(material, BallType::Simple(level).color(&tiers));
+    }
+}
+
+pub fn spawn_ball(
+    commands: &mut Commands,
+    tiers: &BallTiers,
+    current_ball_type: BallType,
+    target_ball_type: Option<BallTarget>,
+    position: Transform,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<ColorMaterial>>,
+) -> Entity {
+    let mut ball;
+    if let Some(target) = target_ball_type {
+        ball = commands.spawn((
+            current_ball_type.mesh(
+                Some(BallType::Simple(target.0).color(tiers)),
+                tiers,
+                meshes,
+                materials,
+            ),
+            current_ball_type,
+            target,
+            BallProgress(0.),
+        ));
+    } else {
+        ball = commands.spawn((
+            current_ball_type.mesh(None, tiers, meshes, materials),
+            current_ball_type,
+        ));
+    }
+    if current_ball_type == BallType::Special {
+        ball.insert(Shimmer);
+    }
+    ball.insert(RigidBody::Dynamic)
+        .insert(current_ball_type.physics(tiers))
+        .insert(Velocity::linear(Vect::new(0.0, -0.0)))
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(TransformBundle::from(position))
+        .id()
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum BallType {
+    Simple(i32),
+    Special,
+    Bomb,
+}
+
+impl BallType {
+    pub fn mesh(
+        self,
+        target_color: Option<ColorMaterial>,
+        tiers: &BallTiers,
+        meshes: &mut ResMut<Assets<Mesh>>,
+        materials: &mut ResMut<Assets<ColorMaterial>>,
+    ) -> MaterialMesh2dBundle<ColorMaterial> {
+        MaterialMesh2dBundle {
+            mesh: meshes
+                .add(shape::Circle::new(self.size(tiers)).into())
+                .into(),
+            material: materials.add(if let Some(target_color) = target_color {
+                target_color
+            } else {
+                self.color(tiers)
+            }),
+            ..default()
+        }
+    }
+}
+
+impl BallType {
+    pub fn size(&self, tiers: &BallTiers) -> f32 {
+        match self {
+            Self::Simple(level) => tiers.tier(*level).radius,
+            Self::Special => 10.,
+            Self::Bomb => 9.,
+        }
+    }
+
+    /// The collider and physical properties a ball of this type gets.
+    pub fn physics(
+        &self,
+        tiers: &BallTiers,
+    ) -> (Collider, ColliderMassProperties, Restitution, Friction) {
+        let (density, restitution, friction) = match self {
+            Self::Simple(level) => {
+                let tier = tiers.tier(*level);
+                (tier.density, tier.restitution, tier.friction)
+            }
+            _ => (default_density(), default_restitution(), 0.),
+        };
+        (
+            Collider::ball(self.size(tiers)),
+            ColliderMassProperties::Density(density),
+            Restitution::coefficient(restitution),
+            Friction::coefficient(friction),
+        )
+    }
+}
+
+impl BallType {
+    pub fn color(self, tiers: &BallTiers) -> ColorMaterial {
+        match self {
+            Self::Simple(level) => {
+                let tier = tiers.tier(level);
+                ColorMaterial {
+                    color: tier.color,
+                    texture: tier.texture.clone(),
+                }
+            }
+            Self::Special => ColorMaterial::from(Color::BLACK),
+            Self::Bomb => ColorMaterial::from(Color::DARK_GRAY),
+        }
+    }
+}
@@ -0,0 +1,687 @@
+use bevy::{prelude::*, transform::TransformSystem};
+use bevy_rapier2d::prelude::*;
+use bevy_turborand::prelude::*;
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::{
+    ball::{fuse_system, shimmer_system, Ball, BallTarget, BallType, Shimmer},
+    physics::{
+        grow_system, Contacts, FinalTierClearedEvent, FinalTierReachedEvent, SimulationTick,
+    },
+    rules::GameRules,
+    spawn::Spawner,
+    storage,
+    tiers::{apply_ball_tiers_system, ball_tiers_ready, BallTiers, BallTiersPlugin},
+    BingleSet, LOOKAHEAD_QUEUE_LENGTH, QUEUE_PREVIEW_SCALE, QUEUE_PREVIEW_SPACING,
+    SETTLED_BALL_SPEED,
+};
+
+/// Game state, the dropper and the `AppState` flow between new games and game over.
+pub struct GameFlowPlugin;
+
+impl Plugin for GameFlowPlugin {
+    fn build(&self, app: &mut App) {
+        let options = app
+            .world
+            .get_resource::<GameOptions>()
+            .cloned()
+            .unwrap_or_default();
+        if !app.is_plugin_added::<BallTiersPlugin>() {
+            app.add_plugins(BallTiersPlugin);
+        }
+        if !app.is_plugin_added::<RngPlugin>() {
+            app.add_plugins(match options.seed {
+                Some(seed) => RngPlugin::new().with_rng_seed(seed),
+                None => RngPlugin::default(),
+            });
+        }
+        app.insert_resource(options)
+            .init_resource::<GameRules>()
+            .add_state::<AppState>()
+            .add_event::<BallDroppedEvent>()
+            .add_event::<GameOverEvent>()
+            .configure_sets(Update, BingleSet::Input.run_if(in_state(AppState::Playing)))
+            .add_systems(Startup, setup_dropper)
+            .add_systems(
+                OnEnter(AppState::Playing),
+                new_game_system.run_if(resource_exists::<NewGame>()),
+            )
+            .add_systems(
+                Update,
+                (
+                    finish_loading_system
+                        .after(apply_ball_tiers_system)
+                        .run_if(in_state(AppState::Loading).and_then(ball_tiers_ready)),
+                    clear_new_game_system.run_if(resource_exists::<NewGame>()),
+                    track_max_level_system.run_if(in_state(AppState::Playing)),
+                    track_final_tier_system.run_if(in_state(AppState::Playing)),
+                    drop_cooldown_system
+                        .before(BingleSet::Input)
+                        .run_if(in_state(AppState::Playing)),
+                    shimmer_system,
+                    fuse_system,
+                ),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    countdown_system,
+                    track_top_level_system.after(grow_system),
+                    overflow_system.after(grow_system),
+                )
+                    .in_set(BingleSet::Merge),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    check_game_state.run_if(in_state(AppState::Playing)),
+                    preview_layout_system.before(TransformSystem::TransformPropagate),
+                ),
+            );
+    }
+}
+
+/// Where the app is between screens and games. Gameplay input and physics only run while
+/// `Playing`.
+#[derive(States, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum AppState {
+    #[default]
+    Loading,
+    MainMenu,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+/// Asks for a fresh game the next time the state enters `Playing`, so resuming from `Paused`
+/// carries on where it left off. Only lives for the frame the game starts on.
+#[derive(Resource)]
+pub struct NewGame {
+    /// Start with a fresh seed instead of replaying the current one.
+    pub reroll_seed: bool,
+}
+
+/// Choices made when launching the game. Insert before adding the plugins to override them.
+#[derive(Resource, Clone, Default)]
+pub struct GameOptions {
+    /// Seed for the first game. Random when not set.
+    pub seed: Option<u64>,
+    pub mode: GameMode,
+    /// Start on the main menu instead of going straight into a game.
+    pub show_menu: bool,
+}
+
+/// Rules a game is played by.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum GameMode {
+    /// Balls of the same level merge.
+    #[default]
+    Classic,
+    /// Balls of the same colour merge, even at different levels. Tiers can share a colour, and
+    /// the merged ball ends up one level above the bigger of the two.
+    Colors,
+    /// Classic rules against the clock, over after `GameRules::timed_mode_seconds`.
+    Timed,
+    /// No strikes and no game over, balls that fly out are just gone.
+    Zen,
+    /// Classic rules with a seed that's the same for everyone on the same day.
+    Daily,
+    /// As many points as possible before `GameRules::blitz_mode_seconds` run out, with every
+    /// merge buying a little more time.
+    Blitz,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 6] = [
+        GameMode::Classic,
+        GameMode::Colors,
+        GameMode::Timed,
+        GameMode::Zen,
+        GameMode::Daily,
+        GameMode::Blitz,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Classic => "classic",
+            Self::Colors => "colors",
+            Self::Timed => "timed",
+            Self::Zen => "zen",
+            Self::Daily => "daily",
+            Self::Blitz => "blitz",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<GameMode> {
+        GameMode::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    pub fn counts_strikes(self, rules: &GameRules) -> bool {
+        rules.strike_modes.contains(&self)
+    }
+
+    /// Whether the game is lost by a ball settling above the danger line.
+    pub fn has_danger_line(self, rules: &GameRules) -> bool {
+        rules.overflow_modes.contains(&self)
+    }
+
+    /// Seconds a game lasts, if it's played against the clock.
+    pub fn time_limit(self, rules: &GameRules) -> Option<f32> {
+        match self {
+            Self::Timed => Some(rules.timed_mode_seconds),
+            Self::Blitz => Some(rules.blitz_mode_seconds),
+            _ => None,
+        }
+    }
+
+    /// Seconds each merge adds to the clock.
+    pub fn merge_bonus_seconds(self, rules: &GameRules) -> f32 {
+        match self {
+            Self::Blitz => rules.blitz_merge_bonus_seconds,
+            _ => 0.,
+        }
+    }
+
+    /// Seed for a new game in this mode.
+    pub fn fresh_seed(self, global_rng: &mut GlobalRng) -> u64 {
+        match self {
+            Self::Daily => storage::now() / (60 * 60 * 24),
+            _ => global_rng.u64(..),
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct Game {
+    pub dropper: Dropper,
+    /// Seed the dropper's sequence of balls is generated from.
+    pub seed: u64,
+    pub mode: GameMode,
+    pub strikes: i32,
+    pub interpolated_score: i32,
+    pub score: i32,
+    /// Highest ball level dropped or merged into this game.
+    pub max_level: i32,
+    /// Length of the current merge chain, which multiplies the score of each merge in it.
+    pub combo: i32,
+    /// Seconds left for another merge to carry the combo on.
+    pub combo_window: f32,
+    /// Longest merge chain this game.
+    pub max_combo: i32,
+    /// Balls grown into the final tier this game.
+    pub final_tiers_reached: i32,
+    /// Pairs of final tier balls merged away this game.
+    pub final_tiers_cleared: i32,
+    /// Seconds left in modes played against the clock.
+    pub time_left: f32,
+    /// Seconds merges have added to the clock this game.
+    pub bonus_seconds: f32,
+    /// Seconds a settled ball has been above the danger line, in modes that have one.
+    pub overflow_time: f32,
+    /// Whether the game was picked back up from a save instead of starting from an empty bucket.
+    pub resumed: bool,
+}
+
+#[derive(Component)]
+pub struct Dropper {
+    pub rng: RngComponent,
+    pub next_ball: Ball,
+    pub mesh: Entity,
+    /// Ball put aside with a hold, to be swapped back in by the next one.
+    pub held: Option<BallType>,
+    pub held_mesh: Option<Entity>,
+    /// Whether the hold has been used since the last drop. It only works once per drop.
+    pub hold_used: bool,
+    /// The balls after `next_ball`, in the order they'll come up.
+    pub queue: VecDeque<BallType>,
+    pub queue_meshes: Vec<Entity>,
+    /// Seconds until the next ball can be dropped.
+    pub cooldown: f32,
+    /// Seconds left of the feedback for a drop that was turned down.
+    pub rejected: f32,
+    /// Chances of a bomb and of a wildcard ball, from the `GameRules` the game started with.
+    pub bomb_chance: f64,
+    pub special_chance: f64,
+    pub spawner: Spawner,
+}
+
+/// A small preview of a queued ball, by its place in `Dropper::queue`.
+#[derive(Component)]
+pub struct QueuePreview(pub usize);
+
+/// The preview of the ball in the hold slot.
+#[derive(Component)]
+pub struct HeldPreview;
+
+impl Dropper {
+    /// Moves the first queued ball up to be the next one and swaps the previews to match.
+    pub fn advance(
+        &mut self,
+        tiers: &BallTiers,
+        commands: &mut Commands,
+        meshes: &mut ResMut<Assets<Mesh>>,
+        materials: &mut ResMut<Assets<ColorMaterial>>,
+    ) {
+        self.fill_queue(tiers);
+        let new_ball = self.queue.pop_front().unwrap();
+        self.fill_queue(tiers);
+        self.set_next(new_ball, tiers, commands, meshes, materials);
+        self.spawn_queue_previews(tiers, commands, meshes, materials);
+        self.hold_used = false;
+    }
+
+    fn roll(&mut self, tiers: &BallTiers) -> BallType {
+        if self.rng.chance(self.bomb_chance) {
+            BallType::Bomb
+        } else if self.rng.chance(self.special_chance) {
+            BallType::Special
+        } else {
+            BallType::Simple(self.spawner.roll_level(tiers, &mut self.rng))
+        }
+    }
+
+    /// Tops the queue up from the RNG. The queue is always kept at the same length, so the
+    /// balls a seed hands out don't depend on how many of them are shown.
+    fn fill_queue(&mut self, tiers: &BallTiers) {
+        while self.queue.len() < LOOKAHEAD_QUEUE_LENGTH {
+            let ball_type = self.roll(tiers);
+            self.queue.push_back(ball_type);
+        }
+    }
+
+    /// Replaces the queued balls, topping them up if there are too few, and their previews.
+    pub fn set_queue(
+        &mut self,
+        queue: impl IntoIterator<Item = BallType>,
+        tiers: &BallTiers,
+        commands: &mut Commands,
+        meshes: &mut ResMut<Assets<Mesh>>,
+        materials: &mut ResMut<Assets<ColorMaterial>>,
+    ) {
+        self.queue = queue.into_iter().collect();
+        self.fill_queue(tiers);
+        self.spawn_queue_previews(tiers, commands, meshes, materials);
+    }
+
+    fn spawn_queue_previews(
+        &mut self,
+        tiers: &BallTiers,
+        commands: &mut Commands,
+        meshes: &mut ResMut<Assets<Mesh>>,
+        materials: &mut ResMut<Assets<ColorMaterial>>,
+    ) {
+        for mesh in self.queue_meshes.drain(..) {
+            commands.entity(mesh).despawn();
+        }
+        for (index, ball_type) in self.queue.iter().enumerate() {
+            let preview = spawn_preview(
+                *ball_type,
+                Transform::from_scale(Vec3::splat(QUEUE_PREVIEW_SCALE)),
+                tiers,
+                commands,
+                meshes,
+                materials,
+            );
+            commands.entity(preview).insert(QueuePreview(index));
+            self.queue_meshes.push(preview);
+        }
+    }
+
+    /// Puts the next ball aside and swaps in the one held before, or the ball after it when
+    /// nothing was held yet. Returns false when the hold was already used since the last drop.
+    pub fn hold(
+        &mut self,
+        tiers: &BallTiers,
+        commands: &mut Commands,
+        meshes: &mut ResMut<Assets<Mesh>>,
+        materials: &mut ResMut<Assets<ColorMaterial>>,
+    ) -> bool {
+        if self.hold_used {
+            return false;
+        }
+        let current = self.next_ball.ball_type;
+        match self.held {
+            Some(held) => self.set_next(held, tiers, commands, meshes, materials),
+            None => self.advance(tiers, commands, meshes, materials),
+        }
+        self.set_held(Some(current), tiers, commands, meshes, materials);
+        self.hold_used = true;
+        true
+    }
+
+    /// Replaces the next ball and its upcoming ball preview.
+    pub fn set_next(
+        &mut self,
+        ball_type: BallType,
+        tiers: &BallTiers,
+        commands: &mut Commands,
+        meshes: &mut ResMut<Assets<Mesh>>,
+        materials: &mut ResMut<Assets<ColorMaterial>>,
+    ) {
+        self.next_ball.ball_type = ball_type;
+        // Swap upcoming mesh
+        commands.get_entity(self.mesh).unwrap().despawn();
+        self.mesh = spawn_preview(
+            ball_type,
+            Transform::default(),
+            tiers,
+            commands,
+            meshes,
+            materials,
+        );
+    }
+
+    /// Replaces the held ball and its preview in the hold slot.
+    pub fn set_held(
+        &mut self,
+        held: Option<BallType>,
+        tiers: &BallTiers,
+        commands: &mut Commands,
+        meshes: &mut ResMut<Assets<Mesh>>,
+        materials: &mut ResMut<Assets<ColorMaterial>>,
+    ) {
+        self.held = held;
+        if let Some(mesh) = self.held_mesh.take() {
+            commands.entity(mesh).despawn();
+        }
+        self.held_mesh = held.map(|ball_type| {
+            let preview = spawn_preview(
+                ball_type,
+                Transform::default(),
+                tiers,
+                commands,
+                meshes,
+                materials,
+            );
+            commands.entity(preview).insert(HeldPreview);
+            preview
+        });
+    }
+}
+
+fn spawn_preview(
+    ball_type: BallType,
+    transform: Transform,
+    tiers: &BallTiers,
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<ColorMaterial>>,
+) -> Entity {
+    let mut bundle = ball_type.mesh(None, tiers, meshes, materials);
+    bundle.transform = transform;
+    let mut preview = commands.spawn(bundle);
+    if ball_type == BallType::Special {
+        preview.insert(Shimmer);
+    }
+    preview.id()
+}
+
+/// Why a game ended.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameOverReason {
+    Strikes,
+    Overflow,
+    OutOfTime,
+}
+
+/// Sent once when a game is lost or runs out of time, just before heading to `GameOver`.
+#[derive(Event, Clone, Copy)]
+pub struct GameOverEvent {
+    pub reason: GameOverReason,
+}
+
+/// Sent whenever the player (or a script) drops a ball into play.
+#[derive(Event, Clone, Copy)]
+pub struct BallDroppedEvent {
+    pub x: f32,
+    pub ball_type: BallType,
+    /// Whether the hold was used since the previous drop.
+    pub held: bool,
+}
+
+pub fn setup_dropper(
+    mut commands: Commands,
+    options: Res<GameOptions>,
+    mut global_rng: ResMut<GlobalRng>,
+    rules: Res<GameRules>,
+    tiers: Res<BallTiers>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let seed = options
+        .seed
+        .unwrap_or_else(|| options.mode.fresh_seed(&mut global_rng));
+    let mut rng = RngComponent::with_seed(seed);
+    let first_ball = BallType::Simple(tiers.roll_level(&mut rng));
+    let mesh = commands
+        .spawn(first_ball.mesh(None, &tiers, &mut meshes, &mut materials))
+        .id();
+    commands.insert_resource(Game {
+        dropper: Dropper {
+            rng,
+            next_ball: Ball {
+                ball_type: first_ball,
+            },
+            mesh,
+            held: None,
+            held_mesh: None,
+            hold_used: false,
+            queue: VecDeque::new(),
+            queue_meshes: Vec::new(),
+            cooldown: 0.,
+            rejected: 0.,
+            bomb_chance: rules.bomb_ball_chance,
+            special_chance: rules.special_ball_chance,
+            spawner: Spawner::new(rules.spawn_distribution.clone()),
+        },
+        seed,
+        mode: options.mode,
+        strikes: 0,
+        interpolated_score: 0,
+        score: 0,
+        max_level: 0,
+        combo: 0,
+        combo_window: 0.,
+        max_combo: 0,
+        final_tiers_reached: 0,
+        final_tiers_cleared: 0,
+        time_left: 0.,
+        bonus_seconds: 0.,
+        overflow_time: 0.,
+        resumed: false,
+    });
+}
+
+/// Heads to the main menu, or straight into the first game, as soon as the app is up.
+pub fn finish_loading_system(
+    mut commands: Commands,
+    options: Res<GameOptions>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if options.show_menu {
+        next_state.set(AppState::MainMenu);
+    } else {
+        start_new_game(&mut commands, &mut next_state, false);
+    }
+}
+
+/// Starts a new game, from the game over screen or anywhere else.
+pub fn start_new_game(
+    commands: &mut Commands,
+    next_state: &mut NextState<AppState>,
+    reroll_seed: bool,
+) {
+    commands.insert_resource(NewGame { reroll_seed });
+    next_state.set(AppState::Playing);
+}
+
+pub fn clear_new_game_system(mut commands: Commands) {
+    commands.remove_resource::<NewGame>();
+}
+
+pub fn track_max_level_system(
+    mut game: ResMut<Game>,
+    mut dropped_ev: EventReader<BallDroppedEvent>,
+) {
+    for dropped in dropped_ev.read() {
+        if let BallType::Simple(level) = dropped.ball_type {
+            game.max_level = game.max_level.max(level);
+        }
+    }
+}
+
+pub fn track_final_tier_system(
+    mut game: ResMut<Game>,
+    mut reached_ev: EventReader<FinalTierReachedEvent>,
+    mut cleared_ev: EventReader<FinalTierClearedEvent>,
+) {
+    game.final_tiers_reached += reached_ev.read().count() as i32;
+    game.final_tiers_cleared += cleared_ev.read().count() as i32;
+}
+
+pub fn countdown_system(time: Res<Time>, rules: Res<GameRules>, mut game: ResMut<Game>) {
+    if game.mode.time_limit(&rules).is_some() {
+        game.time_left = (game.time_left - time.delta_seconds()).max(0.);
+    }
+}
+
+/// Lays the upcoming, held and queued ball previews out beside the bucket. The upcoming ball
+/// can be moved on from here, e.g. over the aim.
+#[allow(clippy::type_complexity)]
+pub fn preview_layout_system(
+    game: Option<Res<Game>>,
+    rules: Res<GameRules>,
+    mut held: Query<&mut Transform, (With<HeldPreview>, Without<QueuePreview>)>,
+    mut queued: Query<(&mut Transform, &QueuePreview), Without<HeldPreview>>,
+    mut previews: Query<&mut Transform, (Without<HeldPreview>, Without<QueuePreview>)>,
+) {
+    if let Some(game) = game {
+        if let Ok(mut transform) = previews.get_mut(game.dropper.mesh) {
+            transform.translation = rules.upcoming_ball_position();
+        }
+    }
+    for mut transform in held.iter_mut() {
+        transform.translation = rules.held_ball_position();
+    }
+    // Stack them upwards from just above the upcoming ball
+    for (mut transform, preview) in queued.iter_mut() {
+        transform.translation =
+            rules.queue_preview_position() + Vec3::Y * QUEUE_PREVIEW_SPACING * preview.0 as f32;
+    }
+}
+
+/// Keeps track of the biggest ball in the bucket for spawn distributions that follow it.
+pub fn track_top_level_system(
+    mut game: ResMut<Game>,
+    balls: Query<(&BallType, Option<&BallTarget>)>,
+) {
+    let top_level = balls
+        .iter()
+        .filter_map(|(ball_type, target)| match (ball_type, target) {
+            (_, Some(target)) => Some(target.0),
+            (BallType::Simple(level), None) => Some(*level),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    game.dropper.spawner.top_level = top_level;
+}
+
+/// Runs down the dropper's cooldown and the feedback for a turned down drop.
+pub fn drop_cooldown_system(time: Res<Time>, mut game: ResMut<Game>) {
+    let dt = time.delta_seconds();
+    game.dropper.cooldown = (game.dropper.cooldown - dt).max(0.);
+    game.dropper.rejected = (game.dropper.rejected - dt).max(0.);
+}
+
+/// Times how long any settled ball has been sitting above the danger line.
+pub fn overflow_system(
+    time: Res<Time>,
+    rules: Res<GameRules>,
+    mut game: ResMut<Game>,
+    balls: Query<(&Transform, &Velocity), With<BallType>>,
+) {
+    if !game.mode.has_danger_line(&rules) {
+        return;
+    }
+    let line = rules.danger_line_y();
+    let overflowing = balls.iter().any(|(transform, velocity)| {
+        transform.translation.y > line && velocity.linvel.length() < SETTLED_BALL_SPEED
+    });
+    game.overflow_time = if overflowing {
+        game.overflow_time + time.delta_seconds()
+    } else {
+        0.
+    };
+}
+
+pub fn check_game_state(
+    game: Res<Game>,
+    rules: Res<GameRules>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut game_over_ev: EventWriter<GameOverEvent>,
+) {
+    let reason = if game.mode.counts_strikes(&rules) && game.strikes >= rules.strike_limit {
+        GameOverReason::Strikes
+    } else if game.mode.has_danger_line(&rules)
+        && game.overflow_time >= rules.overflow_grace_seconds
+    {
+        GameOverReason::Overflow
+    } else if game.mode.time_limit(&rules).is_some() && game.time_left <= 0. {
+        GameOverReason::OutOfTime
+    } else {
+        return;
+    };
+    game_over_ev.send(GameOverEvent { reason });
+    next_state.set(AppState::GameOver);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn new_game_system(
+    mut game: ResMut<Game>,
+    mut commands: Commands,
+    balls: Query<Entity, With<BallType>>,
+    new_game: Res<NewGame>,
+    mut contacts: ResMut<Contacts>,
+    mut tick: ResMut<SimulationTick>,
+    mut global_rng: ResMut<GlobalRng>,
+    rules: Res<GameRules>,
+    tiers: Res<BallTiers>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    if new_game.reroll_seed {
+        game.seed = game.mode.fresh_seed(&mut global_rng);
+    }
+    // Start the ball sequence over so the same seed always plays out the same way
+    game.dropper.rng = RngComponent::with_seed(game.seed);
+    game.dropper.bomb_chance = rules.bomb_ball_chance;
+    game.dropper.special_chance = rules.special_ball_chance;
+    game.dropper.spawner.reset(rules.spawn_distribution.clone());
+    game.dropper.queue.clear();
+    game.dropper
+        .advance(&tiers, &mut commands, &mut meshes, &mut materials);
+    game.dropper
+        .set_held(None, &tiers, &mut commands, &mut meshes, &mut materials);
+    game.dropper.cooldown = 0.;
+    game.dropper.rejected = 0.;
+    game.score = 0;
+    game.max_level = 0;
+    game.combo = 0;
+    game.combo_window = 0.;
+    game.max_combo = 0;
+    game.final_tiers_reached = 0;
+    game.final_tiers_cleared = 0;
+    game.time_left = game.mode.time_limit(&rules).unwrap_or(0.);
+    game.bonus_seconds = 0.;
+    game.overflow_time = 0.;
+    game.strikes = 0;
+    game.resumed = false;
+    for entity in balls.iter() {
+        commands.entity(entity).despawn();
+    }
+    contacts.0.drain();
+    tick.0 = 0;
+}
@@ -0,0 +1,116 @@
+use bevy::{app::AppExit, prelude::*, time::TimeUpdateStrategy};
+use std::time::Duration;
+
+use crate::{
+    game::{check_game_state, AppState, BallDroppedEvent, Game},
+    input::{click, DropParams},
+    replay::ReplayPlayback,
+    rules::GameRules,
+    BingleSet,
+};
+
+const HEADLESS_FRAME_SECONDS: f32 = 1. / 60.;
+const HEADLESS_SETTLE_FRAMES: u32 = 300;
+
+/// Runs the physics and merge logic without a window, dropping balls from a script
+/// and printing the final result once the script has played out or the game is over.
+///
+/// Meant to be added next to `MinimalPlugins`, [`GameFlowPlugin`](crate::game::GameFlowPlugin)
+/// and [`MergePhysicsPlugin`](crate::physics::MergePhysicsPlugin).
+pub struct HeadlessPlugin {
+    pub positions: Vec<f32>,
+    pub drop_interval: u32,
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((TransformPlugin, HierarchyPlugin, AssetPlugin::default()))
+            .init_asset::<Mesh>()
+            .init_asset::<ColorMaterial>()
+            // Ball tiers may point at textures
+            .init_asset::<Image>()
+            // Advance time by a fixed amount per frame so runs don't depend on the host's speed
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(
+                HEADLESS_FRAME_SECONDS,
+            )))
+            .insert_resource(DropScript {
+                positions: self.positions.clone(),
+                next: 0,
+                interval: self.drop_interval,
+                frame: 0,
+                last_drop_frame: 0,
+                dropped: 0,
+            })
+            .add_systems(Update, scripted_drop_system.in_set(BingleSet::Input))
+            .add_systems(PostUpdate, headless_report_system.after(check_game_state));
+    }
+}
+
+/// Drop positions fed to `click` by the headless runner.
+#[derive(Resource)]
+pub struct DropScript {
+    pub positions: Vec<f32>,
+    pub next: usize,
+    pub interval: u32,
+    pub frame: u32,
+    pub last_drop_frame: u32,
+    /// Balls that actually made it into play, from the script or a replay.
+    pub dropped: usize,
+}
+
+pub fn scripted_drop_system(mut drop: DropParams, mut script: ResMut<DropScript>) {
+    script.frame += 1;
+    if script.next < script.positions.len()
+        && script.frame - script.last_drop_frame >= script.interval
+    {
+        let position = script.positions[script.next];
+        script.next += 1;
+        script.last_drop_frame = script.frame;
+        click(&mut drop, Vec2::new(position, 0.));
+    }
+}
+
+pub fn headless_report_system(
+    game: Res<Game>,
+    rules: Res<GameRules>,
+    state: Res<State<AppState>>,
+    mut script: ResMut<DropScript>,
+    playback: Option<Res<ReplayPlayback>>,
+    mut dropped_ev: EventReader<BallDroppedEvent>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let dropped = dropped_ev.read().count();
+    if dropped > 0 {
+        script.dropped += dropped;
+        script.last_drop_frame = script.frame;
+    }
+    let script_done = script.next >= script.positions.len()
+        && playback.is_none_or(|playback| playback.finished())
+        && script.frame - script.last_drop_frame >= HEADLESS_SETTLE_FRAMES;
+    let over = *state.get() == AppState::GameOver;
+    if over || script_done {
+        let seed = game.seed;
+        let score = game.score;
+        let strikes = game.strikes;
+        let max_combo = game.max_combo;
+        let final_tiers_reached = game.final_tiers_reached;
+        let final_tiers_cleared = game.final_tiers_cleared;
+        let drops = script.dropped;
+        let frames = script.frame;
+        println!("seed: {seed}");
+        println!("mode: {}", game.mode.name());
+        println!("drops: {drops}");
+        println!("frames: {frames}");
+        println!("score: {score}");
+        println!("strikes: {strikes}/{}", rules.strike_limit);
+        println!("max combo: {max_combo}");
+        println!("final tiers reached: {final_tiers_reached}");
+        println!("final tiers cleared: {final_tiers_cleared}");
+        if game.mode.time_limit(&rules).is_some() {
+            println!("time left: {:.1}s", game.time_left);
+            println!("bonus time: {:.1}s", game.bonus_seconds);
+        }
+        println!("game over: {over}");
+        exit.send(AppExit);
+    }
+}
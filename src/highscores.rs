@@ -0,0 +1,220 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    game::{AppState, Game, GameMode},
+    storage,
+};
+
+const HIGH_SCORE_COUNT: usize = 10;
+const HIGH_SCORE_STORAGE_NAME: &str = "highscores";
+
+/// Keeps the best scores of each mode across sessions and ranks each finished game against the
+/// ones of its mode.
+pub struct HighScorePlugin;
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(HighScores::load())
+            .add_systems(OnEnter(AppState::GameOver), record_high_score_system);
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Default)]
+pub struct HighScores {
+    /// Best first, up to `HIGH_SCORE_COUNT` of each mode.
+    pub entries: Vec<HighScoreEntry>,
+    /// Where the last finished game placed among its mode's, if it made the table.
+    #[serde(skip)]
+    pub last_rank: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HighScoreEntry {
+    pub score: i32,
+    /// Seconds since the unix epoch.
+    pub date: u64,
+    pub seed: u64,
+    pub max_level: i32,
+    #[serde(default)]
+    pub max_combo: i32,
+    /// Balls grown into the final tier.
+    #[serde(default)]
+    pub final_tiers_reached: i32,
+    /// Pairs of final tier balls merged away.
+    #[serde(default)]
+    pub final_tiers_cleared: i32,
+    #[serde(default)]
+    pub mode: GameMode,
+}
+
+impl HighScores {
+    pub fn load() -> HighScores {
+        storage::load(HIGH_SCORE_STORAGE_NAME)
+            .and_then(|contents| ron::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| e.to_string())
+            .and_then(|contents| storage::save(HIGH_SCORE_STORAGE_NAME, &contents));
+        if let Err(error) = result {
+            error!("Couldn't save high scores: {error}");
+        }
+    }
+
+    /// The table of a single mode, best first.
+    pub fn mode_entries(&self, mode: GameMode) -> impl Iterator<Item = &HighScoreEntry> {
+        self.entries.iter().filter(move |entry| entry.mode == mode)
+    }
+
+    pub fn best(&self, mode: GameMode) -> i32 {
+        self.mode_entries(mode)
+            .next()
+            .map_or(0, |entry| entry.score)
+    }
+
+    /// Adds the entry if it makes its mode's table and returns its rank there.
+    pub fn insert(&mut self, entry: HighScoreEntry) -> Option<usize> {
+        let mode = entry.mode;
+        let rank = self
+            .mode_entries(mode)
+            .position(|existing| entry.score > existing.score)
+            .unwrap_or_else(|| self.mode_entries(mode).count());
+        if rank >= HIGH_SCORE_COUNT {
+            return None;
+        }
+        let index = self
+            .entries
+            .iter()
+            .position(|existing| entry.score > existing.score)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(index, entry);
+        // Knock the mode's lowest out of a full table
+        if let Some(index) = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, existing)| existing.mode == mode)
+            .nth(HIGH_SCORE_COUNT)
+            .map(|(index, _)| index)
+        {
+            self.entries.remove(index);
+        }
+        Some(rank)
+    }
+}
+
+pub fn record_high_score_system(game: Res<Game>, mut high_scores: ResMut<HighScores>) {
+    high_scores.last_rank = high_scores.insert(HighScoreEntry {
+        score: game.score,
+        date: storage::now(),
+        seed: game.seed,
+        max_level: game.max_level,
+        max_combo: game.max_combo,
+        final_tiers_reached: game.final_tiers_reached,
+        final_tiers_cleared: game.final_tiers_cleared,
+        mode: game.mode,
+    });
+    if high_scores.last_rank.is_some() {
+        high_scores.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(score: i32) -> HighScoreEntry {
+        HighScoreEntry {
+            score,
+            date: 0,
+            seed: score as u64,
+            max_level: 1,
+            max_combo: 0,
+            final_tiers_reached: 0,
+            final_tiers_cleared: 0,
+            mode: GameMode::Classic,
+        }
+    }
+
+    /// A full table of 100, 90 .. 10.
+    fn full_table() -> HighScores {
+        let mut high_scores = HighScores::default();
+        for score in (1..=HIGH_SCORE_COUNT as i32).rev() {
+            high_scores.insert(entry(score * 10));
+        }
+        high_scores
+    }
+
+    fn scores(high_scores: &HighScores) -> Vec<i32> {
+        high_scores
+            .mode_entries(GameMode::Classic)
+            .map(|entry| entry.score)
+            .collect()
+    }
+
+    #[test]
+    fn insert_into_full_table_drops_the_lowest() {
+        let mut high_scores = full_table();
+        assert_eq!(high_scores.insert(entry(55)), Some(5));
+        assert_eq!(high_scores.entries.len(), HIGH_SCORE_COUNT);
+        assert_eq!(
+            scores(&high_scores),
+            [100, 90, 80, 70, 60, 55, 50, 40, 30, 20]
+        );
+    }
+
+    #[test]
+    fn new_best_ranks_first() {
+        let mut high_scores = full_table();
+        assert_eq!(high_scores.insert(entry(150)), Some(0));
+        assert_eq!(high_scores.best(GameMode::Classic), 150);
+        assert_eq!(HighScores::default().insert(entry(0)), Some(0));
+    }
+
+    #[test]
+    fn ties_rank_below_the_existing_score() {
+        let mut high_scores = full_table();
+        let rank = high_scores.insert(HighScoreEntry {
+            seed: 1234,
+            ..entry(100)
+        });
+        assert_eq!(rank, Some(1));
+        assert_eq!(high_scores.entries[0].seed, 100);
+        assert_eq!(high_scores.entries[1].seed, 1234);
+        // Tying the lowest score of a full table doesn't make it
+        assert_eq!(high_scores.insert(entry(20)), None);
+    }
+
+    #[test]
+    fn modes_are_ranked_apart() {
+        let mut high_scores = full_table();
+        let blitz = |score| HighScoreEntry {
+            mode: GameMode::Blitz,
+            ..entry(score)
+        };
+        // Below every classic score, but the first blitz one
+        assert_eq!(high_scores.insert(blitz(5)), Some(0));
+        assert_eq!(high_scores.best(GameMode::Blitz), 5);
+        assert_eq!(high_scores.best(GameMode::Classic), 100);
+        for score in 1..=HIGH_SCORE_COUNT as i32 {
+            high_scores.insert(blitz(score * 100));
+        }
+        assert_eq!(
+            high_scores.mode_entries(GameMode::Blitz).count(),
+            HIGH_SCORE_COUNT
+        );
+        assert_eq!(high_scores.insert(blitz(5)), None);
+        assert_eq!(scores(&high_scores), scores(&full_table()));
+        assert_eq!(high_scores.best(GameMode::Zen), 0);
+    }
+
+    #[test]
+    fn below_the_cutoff_has_no_rank() {
+        let mut high_scores = full_table();
+        assert_eq!(high_scores.insert(entry(5)), None);
+        assert_eq!(scores(&high_scores), scores(&full_table()));
+    }
+}
@@ -0,0 +1,496 @@
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::*,
+    sprite::MaterialMesh2dBundle,
+    transform::TransformSystem,
+    window::{CursorLeft, PrimaryWindow},
+};
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    ball::{spawn_ball, BallType},
+    game::{preview_layout_system, start_new_game, AppState, BallDroppedEvent, Game},
+    overlay::MainCamera,
+    rules::GameRules,
+    settings::Settings,
+    tiers::BallTiers,
+    BingleSet, AIM_GUIDE_MAX_LENGTH, DROP_REJECTED_FLASH_SECONDS, HOLD_SLOT_RADIUS,
+};
+
+/// Mouse, touch, keyboard and gamepad input that drops and holds balls, and restarts from the
+/// game over screen.
+pub struct DropInputPlugin;
+
+impl Plugin for DropInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CursorWorldPosition>()
+            .init_resource::<PointerPress>()
+            .init_resource::<DropperAim>()
+            .add_systems(Startup, setup_aim_marker)
+            .add_systems(OnEnter(AppState::Playing), reset_pointer_press_system)
+            .add_systems(
+                Update,
+                (
+                    my_cursor_system,
+                    mouse_click_system.after(my_cursor_system),
+                    touch_events_system.after(my_cursor_system),
+                    mouse_aim_system.after(my_cursor_system),
+                    aim_system.after(mouse_aim_system),
+                    hold_system,
+                )
+                    .in_set(BingleSet::Input),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    dropper_preview_system
+                        .after(preview_layout_system)
+                        .before(TransformSystem::TransformPropagate),
+                    drop_feedback_system.after(dropper_preview_system),
+                ),
+            )
+            .add_systems(
+                Update,
+                game_over_input_system.run_if(in_state(AppState::GameOver)),
+            );
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct CursorWorldPosition(pub Vec2);
+
+/// Where the next ball will drop.
+#[derive(Resource, Default)]
+pub struct DropperAim {
+    pub x: f32,
+    /// Whether the mouse, keyboard or a gamepad is aiming, so the dropper should follow it.
+    pub active: bool,
+}
+
+const DROP_REJECTED_COLOR: Color = Color::rgba(0.5, 0.5, 0.5, 0.5);
+
+#[derive(Component)]
+pub struct AimMarker;
+
+/// The line from the upcoming ball down to where it would land.
+#[derive(Component)]
+pub struct AimGuide;
+
+/// The mouse or touch press a drop is waiting on. Only presses that start mid-game and away from
+/// any button drop a ball, so a tap on a menu or the pause button never lets one go.
+#[derive(Resource, Default)]
+pub struct PointerPress {
+    pub mouse: bool,
+    pub touch: Option<u64>,
+}
+
+pub fn reset_pointer_press_system(mut press: ResMut<PointerPress>) {
+    *press = PointerPress::default();
+}
+
+fn over_button(buttons: &Query<&Interaction, With<Button>>) -> bool {
+    buttons
+        .iter()
+        .any(|interaction| *interaction != Interaction::None)
+}
+
+pub fn my_cursor_system(
+    mut mycoords: ResMut<CursorWorldPosition>,
+    // query to get the window (so we can read the current cursor position)
+    q_window: Query<&Window, With<PrimaryWindow>>,
+    // query to get camera transform
+    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    // get the camera info and transform
+    // assuming there is exactly one main camera entity, so Query::single() is OK
+    let (camera, camera_transform) = q_camera.single();
+
+    // There is only one primary window, so we can similarly get it from the query:
+    let window = q_window.single();
+
+    // check if the cursor is inside the window and get its position
+    // then, ask bevy to convert into world coordinates, and truncate to discard Z
+    if let Some(world_position) = window
+        .cursor_position()
+        .and_then(|cursor| camera.viewport_to_world(camera_transform, cursor))
+        .map(|ray| ray.origin.truncate())
+    {
+        mycoords.0 = world_position;
+    }
+}
+
+pub fn setup_aim_marker(
+    mut commands: Commands,
+    rules: Res<GameRules>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.spawn((
+        MaterialMesh2dBundle {
+            mesh: meshes.add(shape::RegularPolygon::new(8., 3).into()).into(),
+            material: materials.add(ColorMaterial::from(Color::WHITE)),
+            // Point down at the drop
+            transform: Transform::from_xyz(0., rules.dropper_y() + 25., 1.)
+                .with_rotation(Quat::from_rotation_z(std::f32::consts::PI)),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        AimMarker,
+    ));
+    commands.spawn((
+        MaterialMesh2dBundle {
+            mesh: meshes
+                .add(shape::Quad::new(Vec2::new(2., 1.)).into())
+                .into(),
+            material: materials.add(ColorMaterial::from(Color::rgba(1., 1., 1., 0.35))),
+            // Behind the balls it points at
+            transform: Transform::from_xyz(0., 0., -1.),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        AimGuide,
+    ));
+}
+
+/// Moves the aim with the arrow keys, A / D or a gamepad's left stick & d-pad, and drops with
+/// Space or the gamepad's south button.
+#[allow(clippy::too_many_arguments)]
+pub fn aim_system(
+    time: Res<Time>,
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    settings: Option<Res<Settings>>,
+    mut aim: ResMut<DropperAim>,
+    mut drop: DropParams,
+) {
+    let mut direction = 0.;
+    if keys.any_pressed([KeyCode::Left, KeyCode::A]) {
+        direction -= 1.;
+    }
+    if keys.any_pressed([KeyCode::Right, KeyCode::D]) {
+        direction += 1.;
+    }
+    let mut dropped = keys.just_pressed(KeyCode::Space);
+    for gamepad in gamepads.iter() {
+        direction += gamepad_axes
+            .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
+            .unwrap_or(0.);
+        if gamepad_buttons.pressed(GamepadButton::new(gamepad, GamepadButtonType::DPadLeft)) {
+            direction -= 1.;
+        }
+        if gamepad_buttons.pressed(GamepadButton::new(gamepad, GamepadButtonType::DPadRight)) {
+            direction += 1.;
+        }
+        dropped |=
+            gamepad_buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::South));
+    }
+    let direction = direction.clamp(-1., 1.);
+    if direction != 0. || dropped {
+        aim.active = true;
+    }
+    let speed = settings.map_or(Settings::default().aim_speed, |settings| settings.aim_speed);
+    aim.x = drop
+        .rules
+        .clamp_drop_x(aim.x + direction * speed * time.delta_seconds());
+    if dropped {
+        click(&mut drop, Vec2::new(aim.x, 0.));
+    }
+}
+
+/// Follows the mouse with the aim while it moves over the window.
+pub fn mouse_aim_system(
+    rules: Res<GameRules>,
+    mut aim: ResMut<DropperAim>,
+    mouse_pos: Res<CursorWorldPosition>,
+    mut cursor_ev: EventReader<CursorMoved>,
+    mut cursor_left_ev: EventReader<CursorLeft>,
+) {
+    if cursor_ev.read().count() > 0 {
+        aim.x = rules.clamp_drop_x(mouse_pos.0.x);
+        aim.active = true;
+    }
+    if cursor_left_ev.read().count() > 0 {
+        aim.active = false;
+    }
+}
+
+/// Holds the upcoming ball over the aim, with a guide line down to whatever it would land on.
+/// Otherwise the ball waits beside the bucket where `preview_layout_system` put it.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn dropper_preview_system(
+    aim: Res<DropperAim>,
+    state: Res<State<AppState>>,
+    game: Option<Res<Game>>,
+    rules: Res<GameRules>,
+    tiers: Res<BallTiers>,
+    rapier_context: Option<Res<RapierContext>>,
+    mut marker: Query<(&mut Transform, &mut Visibility), With<AimMarker>>,
+    mut guide: Query<(&mut Transform, &mut Visibility), (With<AimGuide>, Without<AimMarker>)>,
+    mut previews: Query<&mut Transform, (Without<AimMarker>, Without<AimGuide>)>,
+) {
+    let aiming = aim.active && *state.get() == AppState::Playing;
+    let visibility = if aiming {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+    for (mut transform, mut marker_visibility) in marker.iter_mut() {
+        transform.translation.x = aim.x;
+        *marker_visibility = visibility;
+    }
+    let Some(game) = game else {
+        return;
+    };
+    let dropper_y = rules.dropper_y();
+    if let Some(mut transform) = previews.get_mut(game.dropper.mesh).ok().filter(|_| aiming) {
+        transform.translation.x = aim.x;
+        transform.translation.y = dropper_y;
+    }
+    let size = game.dropper.next_ball.ball_type.size(&tiers);
+    // Sweep the ball straight down to the first thing it would touch
+    let fall = rapier_context.filter(|_| aiming).and_then(|context| {
+        context
+            .cast_shape(
+                Vec2::new(aim.x, dropper_y),
+                0.,
+                Vec2::NEG_Y,
+                &Collider::ball(size),
+                AIM_GUIDE_MAX_LENGTH,
+                true,
+                QueryFilter::default(),
+            )
+            .map(|(_, toi)| toi.toi)
+    });
+    for (mut transform, mut guide_visibility) in guide.iter_mut() {
+        match fall {
+            Some(distance) if distance > 0. => {
+                // The line runs from the bottom of the ball to the bottom of where it lands
+                transform.translation.x = aim.x;
+                transform.translation.y = dropper_y - size - distance * 0.5;
+                transform.scale.y = distance;
+                *guide_visibility = Visibility::Visible;
+            }
+            _ => *guide_visibility = Visibility::Hidden,
+        }
+    }
+}
+
+/// Greys the upcoming ball out for a moment when a drop is turned down, and rings it while the
+/// dropper cools down.
+pub fn drop_feedback_system(
+    game: Option<Res<Game>>,
+    rules: Res<GameRules>,
+    tiers: Res<BallTiers>,
+    state: Res<State<AppState>>,
+    previews: Query<(&Transform, &Handle<ColorMaterial>)>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut gizmos: Gizmos,
+) {
+    let Some(game) = game else {
+        return;
+    };
+    let Ok((transform, material)) = previews.get(game.dropper.mesh) else {
+        return;
+    };
+    let ball_type = game.dropper.next_ball.ball_type;
+    if let Some(material) = materials.get_mut(material) {
+        if game.dropper.rejected > 0. {
+            material.color = DROP_REJECTED_COLOR;
+        } else if material.color == DROP_REJECTED_COLOR {
+            material.color = ball_type.color(&tiers).color;
+        }
+    }
+    let dropper = &game.dropper;
+    if dropper.cooldown > 0. && *state.get() == AppState::Playing {
+        // The ring closes as the dropper gets ready again
+        let remaining = dropper.cooldown / rules.drop_cooldown_seconds;
+        gizmos.arc_2d(
+            transform.translation.truncate(),
+            0.,
+            std::f32::consts::TAU * remaining,
+            ball_type.size(&tiers) + 4.,
+            Color::WHITE,
+        );
+    }
+}
+
+/// Dragging the first finger down aims the dropper, lifting it drops the ball there and a cancelled
+/// touch drops nothing. Other fingers are ignored until the first one is lifted.
+pub fn touch_events_system(
+    touches: Res<Touches>,
+    mut press: ResMut<PointerPress>,
+    mut aim: ResMut<DropperAim>,
+    buttons: Query<&Interaction, With<Button>>,
+    mut drop: DropParams,
+    q_camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+) {
+    if press.touch.is_none() && !over_button(&buttons) {
+        press.touch = touches.iter_just_pressed().next().map(|touch| touch.id());
+    }
+    let Some(id) = press.touch else {
+        return;
+    };
+    let (camera, camera_transform) = q_camera.single();
+    let to_world = |position: Vec2| {
+        camera
+            .viewport_to_world(camera_transform, position)
+            .map(|ray| ray.origin.truncate())
+    };
+    if touches.just_canceled(id) {
+        press.touch = None;
+        aim.active = false;
+    } else if let Some(touch) = touches.get_released(id) {
+        press.touch = None;
+        let aiming = std::mem::take(&mut aim.active);
+        if let Some(world_position) = to_world(touch.position()) {
+            click_or_hold(&mut drop, aiming, world_position);
+        }
+    } else if let Some(touch) = touches.get_pressed(id) {
+        // A tap on the hold slots shouldn't drag the dropper around
+        let on_slot = to_world(touch.start_position())
+            .is_some_and(|position| on_hold_slot(&drop, aim.active, position));
+        if let Some(world_position) = to_world(touch.position()).filter(|_| !on_slot) {
+            aim.x = drop.rules.clamp_drop_x(world_position.x);
+            aim.active = true;
+        }
+    }
+}
+
+pub fn mouse_click_system(
+    mouse_button: Res<Input<MouseButton>>,
+    mouse_pos: Res<CursorWorldPosition>,
+    aim: Res<DropperAim>,
+    mut press: ResMut<PointerPress>,
+    buttons: Query<&Interaction, With<Button>>,
+    mut drop: DropParams,
+) {
+    if mouse_button.just_pressed(MouseButton::Left) && !over_button(&buttons) {
+        press.mouse = true;
+    }
+    if mouse_button.just_released(MouseButton::Left) && std::mem::take(&mut press.mouse) {
+        click_or_hold(&mut drop, aim.active, mouse_pos.0);
+    }
+}
+
+/// C, either Shift or the gamepad's west button holds the next ball.
+pub fn hold_system(
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut drop: DropParams,
+) {
+    let held = keys.any_just_pressed([KeyCode::C, KeyCode::ShiftLeft, KeyCode::ShiftRight])
+        || gamepads.iter().any(|gamepad| {
+            gamepad_buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::West))
+        });
+    if held {
+        hold(&mut drop);
+    }
+}
+
+/// Clicking or tapping the game over screen starts a new game, R replays the same seed and
+/// Escape heads back to the main menu.
+pub fn game_over_input_system(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mouse_button: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(AppState::MainMenu);
+    } else if keys.just_pressed(KeyCode::R) {
+        start_new_game(&mut commands, &mut next_state, false);
+    } else if mouse_button.just_released(MouseButton::Left) || touches.any_just_released() {
+        start_new_game(&mut commands, &mut next_state, true);
+    }
+}
+
+/// Everything `click` needs to drop a ball.
+#[derive(SystemParam)]
+pub struct DropParams<'w, 's> {
+    commands: Commands<'w, 's>,
+    existing_balls: Query<'w, 's, (&'static Transform, &'static Collider), With<BallType>>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<ColorMaterial>>,
+    game: ResMut<'w, Game>,
+    rules: Res<'w, GameRules>,
+    tiers: Res<'w, BallTiers>,
+    dropped_ev: EventWriter<'w, BallDroppedEvent>,
+}
+
+/// Whether a press landed on a ball waiting beside the bucket. The slots overlap the far left of
+/// the drop range, so an empty slot, or the upcoming ball's while it's over the aim, doesn't count.
+fn on_hold_slot(drop: &DropParams, aiming: bool, position: Vec2) -> bool {
+    let upcoming = (!aiming).then(|| drop.rules.upcoming_ball_position());
+    let held = drop
+        .game
+        .dropper
+        .held
+        .map(|_| drop.rules.held_ball_position());
+    upcoming
+        .into_iter()
+        .chain(held)
+        .any(|slot| slot.truncate().distance(position) < HOLD_SLOT_RADIUS)
+}
+
+/// Holds the next ball when the press landed on the upcoming or held ball, otherwise drops it.
+fn click_or_hold(drop: &mut DropParams, aiming: bool, click_position: Vec2) {
+    if on_hold_slot(drop, aiming, click_position) {
+        hold(drop);
+    } else {
+        click(drop, click_position);
+    }
+}
+
+pub fn hold(drop: &mut DropParams) {
+    drop.game.dropper.hold(
+        &drop.tiers,
+        &mut drop.commands,
+        &mut drop.meshes,
+        &mut drop.materials,
+    );
+}
+
+/// Drops the next ball above `click_position`, unless the dropper is still cooling down or the
+/// ball would come out overlapping one already there.
+pub fn click(drop: &mut DropParams, click_position: Vec2) {
+    let current_ball_type = drop.game.dropper.next_ball.ball_type;
+    let held = drop.game.dropper.hold_used;
+    let position = drop.rules.clamp_drop_x(click_position.x);
+    let dropper_y = drop.rules.dropper_y();
+    let spawn_position = Vec2::new(position, dropper_y);
+    let size = current_ball_type.size(&drop.tiers);
+    let blocked = drop.game.dropper.cooldown > 0.
+        || drop.existing_balls.iter().any(|(transform, collider)| {
+            let radius = collider.as_ball().map_or(0., |ball| ball.radius());
+            transform.translation.truncate().distance(spawn_position) < size + radius
+        });
+    if blocked {
+        drop.game.dropper.rejected = DROP_REJECTED_FLASH_SECONDS;
+    } else {
+        drop.game.dropper.cooldown = drop.rules.drop_cooldown_seconds;
+        spawn_ball(
+            &mut drop.commands,
+            &drop.tiers,
+            current_ball_type,
+            None,
+            Transform::from_xyz(position, dropper_y, 0.0),
+            &mut drop.meshes,
+            &mut drop.materials,
+        );
+        drop.game.dropper.advance(
+            &drop.tiers,
+            &mut drop.commands,
+            &mut drop.meshes,
+            &mut drop.materials,
+        );
+        drop.dropped_ev.send(BallDroppedEvent {
+            x: position,
+            ball_type: current_ball_type,
+            held,
+        });
+    }
+}
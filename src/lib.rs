@@ -0,0 +1,82 @@
+//! A ball dropping and merging game built on Bevy and Rapier.
+//!
+//! [`BinglePlugin`] registers everything needed to play inside an existing Bevy app. The
+//! sub-plugins can also be added on their own, e.g. to run the simulation without a window.
+
+use bevy::prelude::*;
+
+pub mod ball;
+pub mod game;
+pub mod headless;
+pub mod highscores;
+pub mod input;
+pub mod menu;
+pub mod overlay;
+pub mod pause;
+pub mod physics;
+pub mod replay;
+pub mod rules;
+pub mod savegame;
+pub mod settings;
+pub mod spawn;
+pub mod storage;
+pub mod tiers;
+
+use game::GameFlowPlugin;
+use highscores::HighScorePlugin;
+use input::DropInputPlugin;
+use menu::MainMenuPlugin;
+use overlay::OverlayPlugin;
+use pause::PausePlugin;
+use physics::MergePhysicsPlugin;
+use savegame::SaveGamePlugin;
+use settings::SettingsPlugin;
+
+pub const UNIVERSAL_SCALE: f32 = 1.;
+/// How many balls after the next one the dropper has lined up.
+pub const LOOKAHEAD_QUEUE_LENGTH: usize = 5;
+pub const QUEUE_PREVIEW_SPACING: f32 = 36. * UNIVERSAL_SCALE;
+pub const QUEUE_PREVIEW_SCALE: f32 = 0.5;
+/// Clicking or tapping this close to the upcoming or held ball holds instead of dropping.
+pub const HOLD_SLOT_RADIUS: f32 = 40. * UNIVERSAL_SCALE;
+pub const BARRIER_PADDING: f32 = 100. * UNIVERSAL_SCALE;
+/// How many times a second a wildcard ball flicks to its next colour.
+pub const SPECIAL_BALL_SHIMMER_RATE: f32 = 6.;
+pub const WALL_THICKNESS: f32 = 20. * UNIVERSAL_SCALE;
+/// How far below the dropper the aim guide looks for somewhere to land.
+pub const AIM_GUIDE_MAX_LENGTH: f32 = 1000. * UNIVERSAL_SCALE;
+/// Balls moving slower than this have settled, as far as the danger line is concerned.
+pub const SETTLED_BALL_SPEED: f32 = 20. * UNIVERSAL_SCALE;
+/// How long the upcoming ball stays greyed out after a drop is turned down.
+pub const DROP_REJECTED_FLASH_SECONDS: f32 = 0.3;
+
+/// Everything needed to play bingle: game flow, physics & merging, input, the UI overlay, the
+/// main menu, pausing, settings, high scores and resuming the last game.
+pub struct BinglePlugin;
+
+impl Plugin for BinglePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            GameFlowPlugin,
+            MergePhysicsPlugin,
+            DropInputPlugin,
+            OverlayPlugin,
+            MainMenuPlugin,
+            PausePlugin,
+            SettingsPlugin,
+            HighScorePlugin,
+            SaveGamePlugin,
+        ));
+    }
+}
+
+/// Ordering of the per-frame gameplay systems across the sub-plugins.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum BingleSet {
+    /// Turning input (or a script) into drops, in `Update`.
+    Input,
+    /// Tracking contacts, merging and growing balls after each physics step, in `FixedUpdate`.
+    Merge,
+    /// Dropping a replay's balls ahead of each physics step, in `FixedUpdate`.
+    Replay,
+}
@@ -0,0 +1,289 @@
+use bevy::prelude::*;
+
+use crate::{
+    game::{start_new_game, AppState, Game, GameMode},
+    highscores::HighScores,
+    overlay::{centered_text, spawn_menu_button},
+    savegame::PendingResume,
+    settings::{Settings, AIM_SPEEDS},
+    LOOKAHEAD_QUEUE_LENGTH,
+};
+
+/// The title screen: picking a mode, continuing a saved game, high scores and settings.
+pub struct MainMenuPlugin;
+
+impl Plugin for MainMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MenuScreen>()
+            .add_systems(OnEnter(AppState::MainMenu), open_menu_system)
+            .add_systems(OnExit(AppState::MainMenu), despawn_menu_system)
+            .add_systems(
+                Update,
+                (
+                    menu_action_system,
+                    spawn_menu_system
+                        .after(menu_action_system)
+                        .run_if(resource_changed::<MenuScreen>()),
+                )
+                    .run_if(in_state(AppState::MainMenu)),
+            );
+    }
+}
+
+/// Which page of the main menu is showing.
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MenuScreen {
+    #[default]
+    Title,
+    /// The table of one mode.
+    HighScores(GameMode),
+    Settings,
+}
+
+#[derive(Component)]
+pub struct MainMenuOverlay;
+
+#[derive(Component, Clone, Copy)]
+pub enum MenuAction {
+    Continue,
+    Play(GameMode),
+    Open(MenuScreen),
+    ToggleAutoPause,
+    CycleAimSpeed,
+    CycleLookahead,
+}
+
+pub fn open_menu_system(mut screen: ResMut<MenuScreen>) {
+    *screen = MenuScreen::Title;
+}
+
+pub fn despawn_menu_system(mut commands: Commands, overlay: Query<Entity, With<MainMenuOverlay>>) {
+    for entity in overlay.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub fn spawn_menu_system(
+    mut commands: Commands,
+    screen: Res<MenuScreen>,
+    overlay: Query<Entity, With<MainMenuOverlay>>,
+    pending: Option<Res<PendingResume>>,
+    high_scores: Option<Res<HighScores>>,
+    settings: Option<Res<Settings>>,
+    asset_server: Res<AssetServer>,
+) {
+    for entity in overlay.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    // fill the entire window
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::BLACK),
+                ..default()
+            },
+            MainMenuOverlay,
+        ))
+        .with_children(|builder| match *screen {
+            MenuScreen::Title => {
+                builder.spawn(centered_text(
+                    "b i n g l e",
+                    80.,
+                    Color::WHITE,
+                    &asset_server,
+                ));
+                if let Some(pending) = pending {
+                    let mode = pending.0.mode.name();
+                    spawn_menu_button(
+                        builder,
+                        &format!("continue {mode}"),
+                        MenuAction::Continue,
+                        &asset_server,
+                    );
+                }
+                builder
+                    .spawn(NodeBundle {
+                        style: Style {
+                            flex_wrap: FlexWrap::Wrap,
+                            justify_content: JustifyContent::Center,
+                            max_width: Val::Px(420.),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|row| {
+                        for mode in GameMode::ALL {
+                            spawn_menu_button(
+                                row,
+                                mode.name(),
+                                MenuAction::Play(mode),
+                                &asset_server,
+                            );
+                        }
+                        spawn_menu_button(
+                            row,
+                            "high scores",
+                            MenuAction::Open(MenuScreen::HighScores(GameMode::default())),
+                            &asset_server,
+                        );
+                        spawn_menu_button(
+                            row,
+                            "settings",
+                            MenuAction::Open(MenuScreen::Settings),
+                            &asset_server,
+                        );
+                    });
+            }
+            MenuScreen::HighScores(mode) => {
+                builder.spawn(centered_text(
+                    "High scores",
+                    60.,
+                    Color::WHITE,
+                    &asset_server,
+                ));
+                // Cycles through the modes' tables
+                let index = GameMode::ALL.iter().position(|other| *other == mode);
+                let next =
+                    GameMode::ALL[index.map_or(0, |index| (index + 1) % GameMode::ALL.len())];
+                spawn_menu_button(
+                    builder,
+                    &format!("mode: {}", mode.name()),
+                    MenuAction::Open(MenuScreen::HighScores(next)),
+                    &asset_server,
+                );
+                let entries = high_scores
+                    .as_ref()
+                    .map(|high_scores| high_scores.mode_entries(mode).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                if entries.is_empty() {
+                    builder.spawn(centered_text(
+                        "No games yet",
+                        25.,
+                        Color::GRAY,
+                        &asset_server,
+                    ));
+                }
+                for (rank, entry) in entries.iter().enumerate() {
+                    let place = rank + 1;
+                    let score = entry.score;
+                    let final_tier = if entry.final_tiers_reached > 0 {
+                        " - final tier"
+                    } else {
+                        ""
+                    };
+                    builder.spawn(centered_text(
+                        format!("{place}. {score}{final_tier}"),
+                        25.,
+                        Color::WHITE,
+                        &asset_server,
+                    ));
+                }
+                spawn_menu_button(
+                    builder,
+                    "back",
+                    MenuAction::Open(MenuScreen::Title),
+                    &asset_server,
+                );
+            }
+            MenuScreen::Settings => {
+                builder.spawn(centered_text("Settings", 60., Color::WHITE, &asset_server));
+                let settings = settings
+                    .map(|settings| settings.clone())
+                    .unwrap_or_default();
+                let auto_pause = settings.auto_pause;
+                spawn_menu_button(
+                    builder,
+                    if auto_pause {
+                        "auto pause: on"
+                    } else {
+                        "auto pause: off"
+                    },
+                    MenuAction::ToggleAutoPause,
+                    &asset_server,
+                );
+                spawn_menu_button(
+                    builder,
+                    &format!("aim speed: {}", settings.aim_speed),
+                    MenuAction::CycleAimSpeed,
+                    &asset_server,
+                );
+                spawn_menu_button(
+                    builder,
+                    &format!("next balls: {}", settings.lookahead),
+                    MenuAction::CycleLookahead,
+                    &asset_server,
+                );
+                spawn_menu_button(
+                    builder,
+                    "back",
+                    MenuAction::Open(MenuScreen::Title),
+                    &asset_server,
+                );
+            }
+        });
+}
+
+pub fn menu_action_system(
+    mut commands: Commands,
+    buttons: Query<(&Interaction, &MenuAction), Changed<Interaction>>,
+    mut screen: ResMut<MenuScreen>,
+    mut game: ResMut<Game>,
+    pending: Option<Res<PendingResume>>,
+    mut settings: Option<ResMut<Settings>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, action) in buttons.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match *action {
+            MenuAction::Continue => {
+                if let Some(pending) = &pending {
+                    game.mode = pending.0.mode;
+                    start_new_game(&mut commands, &mut next_state, false);
+                }
+            }
+            MenuAction::Play(mode) => {
+                commands.remove_resource::<PendingResume>();
+                game.mode = mode;
+                start_new_game(&mut commands, &mut next_state, true);
+            }
+            MenuAction::Open(page) => *screen = page,
+            MenuAction::ToggleAutoPause => {
+                if let Some(settings) = settings.as_mut() {
+                    settings.auto_pause = !settings.auto_pause;
+                    settings.save();
+                }
+                *screen = MenuScreen::Settings;
+            }
+            MenuAction::CycleAimSpeed => {
+                if let Some(settings) = settings.as_mut() {
+                    let next = AIM_SPEEDS
+                        .iter()
+                        .position(|speed| *speed == settings.aim_speed)
+                        .map_or(0, |index| (index + 1) % AIM_SPEEDS.len());
+                    settings.aim_speed = AIM_SPEEDS[next];
+                    settings.save();
+                }
+                *screen = MenuScreen::Settings;
+            }
+            MenuAction::CycleLookahead => {
+                if let Some(settings) = settings.as_mut() {
+                    // Wraps back round to showing none of them
+                    settings.lookahead = (settings.lookahead + 1) % (LOOKAHEAD_QUEUE_LENGTH + 1);
+                    settings.save();
+                }
+                *screen = MenuScreen::Settings;
+            }
+        }
+    }
+}
@@ -0,0 +1,583 @@
+use bevy::{prelude::*, render::view::VisibilitySystems};
+
+use crate::{
+    game::{new_game_system, AppState, Game, GameOverEvent, GameOverReason, NewGame, QueuePreview},
+    highscores::{record_high_score_system, HighScores},
+    physics::{FinalTierClearedEvent, FinalTierReachedEvent},
+    rules::GameRules,
+    settings::Settings,
+    tiers::BallTiers,
+};
+
+const CELEBRATION_SECONDS: f32 = 1.5;
+/// How fast the danger line flashes while a ball is settled above it.
+const DANGER_LINE_FLASH_RATE: f32 = 6.;
+/// How long a ball has to sit above the danger line before it flashes, so freshly dropped balls
+/// passing through don't set it off.
+const DANGER_LINE_WARNING_DELAY: f32 = 0.5;
+
+/// Score / strike overlay and the game over screen.
+pub struct OverlayPlugin;
+
+impl Plugin for OverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_graphics)
+            .add_systems(
+                OnEnter(AppState::Playing),
+                spawn_game_overlay_system
+                    .after(new_game_system)
+                    .run_if(resource_exists::<NewGame>()),
+            )
+            .add_systems(
+                OnEnter(AppState::GameOver),
+                game_over_system.after(record_high_score_system),
+            )
+            .add_systems(OnExit(AppState::GameOver), despawn_game_over_system)
+            .add_systems(
+                Update,
+                (
+                    menu_button_color_system,
+                    spawn_celebration_system,
+                    celebration_system,
+                    danger_line_system.run_if(in_state(AppState::Playing)),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    update_score_system,
+                    text_update_system,
+                    queue_preview_system.before(VisibilitySystems::VisibilityPropagate),
+                ),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct MainCamera;
+
+#[derive(Component)]
+pub struct ScoreText;
+
+/// Time left, beside the score in modes played against the clock.
+#[derive(Component)]
+pub struct TimerText;
+
+#[derive(Component)]
+pub struct StrikeText;
+
+#[derive(Component)]
+pub struct ComboText;
+
+#[derive(Component)]
+pub struct GameOverlay;
+
+/// Rings and a score popping out of where two final tier balls cleared each other out.
+#[derive(Component)]
+pub struct Celebration {
+    pub origin: Vec2,
+    pub age: f32,
+}
+
+#[derive(Component)]
+pub struct GameOverOverlay;
+
+#[derive(Component)]
+pub struct MenuButton;
+
+const MENU_BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const MENU_BUTTON_HOVERED_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+const MENU_BUTTON_PRESSED_COLOR: Color = Color::SEA_GREEN;
+
+pub fn setup_graphics(mut commands: Commands) {
+    commands.spawn((Camera2dBundle::default(), MainCamera));
+}
+
+/// Only shows as many of the queued balls as the settings ask for.
+pub fn queue_preview_system(
+    settings: Option<Res<Settings>>,
+    mut previews: Query<(&QueuePreview, &mut Visibility)>,
+) {
+    let lookahead = settings.map_or(Settings::default().lookahead, |settings| settings.lookahead);
+    for (preview, mut visibility) in previews.iter_mut() {
+        *visibility = if preview.0 < lookahead {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+/// Celebrates a ball growing into the final tier, and louder, two of them clearing out.
+pub fn spawn_celebration_system(
+    mut commands: Commands,
+    mut reached_ev: EventReader<FinalTierReachedEvent>,
+    mut cleared_ev: EventReader<FinalTierClearedEvent>,
+    asset_server: Res<AssetServer>,
+) {
+    let reached = reached_ev
+        .read()
+        .map(|reached| (reached.position, "Final tier!".to_string(), 35.));
+    let cleared = cleared_ev
+        .read()
+        .map(|cleared| (cleared.position, format!("+{}", cleared.score), 50.));
+    for (position, text, font_size) in reached.chain(cleared) {
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    text,
+                    TextStyle {
+                        font: asset_server.load("fonts/kuga.ttf"),
+                        font_size,
+                        color: Color::GOLD,
+                    },
+                ),
+                transform: Transform::from_translation(position.extend(2.)),
+                ..default()
+            },
+            Celebration {
+                origin: position,
+                age: 0.,
+            },
+        ));
+    }
+}
+
+/// Floats the score up and fades it out while rings in every tier's colour spread out behind it.
+pub fn celebration_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    tiers: Res<BallTiers>,
+    mut celebrations: Query<(Entity, &mut Celebration, &mut Transform, &mut Text)>,
+    mut gizmos: Gizmos,
+) {
+    for (entity, mut celebration, mut transform, mut text) in celebrations.iter_mut() {
+        celebration.age += time.delta_seconds();
+        let progress = celebration.age / CELEBRATION_SECONDS;
+        if progress >= 1. {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        transform.translation.y = celebration.origin.y + progress * 60.;
+        text.sections[0].style.color.set_a(1. - progress);
+        for (index, tier) in tiers.tiers.iter().enumerate() {
+            let radius = (progress * 200. - index as f32 * 12.).max(0.);
+            gizmos.circle_2d(
+                celebration.origin,
+                radius,
+                *tier.color.clone().set_a(1. - progress),
+            );
+        }
+    }
+}
+
+/// Draws the danger line in modes that have one, flashing it once a ball has settled above it.
+pub fn danger_line_system(
+    time: Res<Time>,
+    game: Option<Res<Game>>,
+    rules: Res<GameRules>,
+    mut gizmos: Gizmos,
+) {
+    let Some(game) = game else {
+        return;
+    };
+    if !game.mode.has_danger_line(&rules) {
+        return;
+    }
+    let color = if game.overflow_time > DANGER_LINE_WARNING_DELAY {
+        let flash = (time.elapsed_seconds() * DANGER_LINE_FLASH_RATE).sin() * 0.5 + 0.5;
+        *Color::RED.clone().set_a(0.4 + flash * 0.6)
+    } else {
+        *Color::RED.clone().set_a(0.25)
+    };
+    let y = rules.danger_line_y();
+    let half_width = rules.bucket_width / 2.;
+    gizmos.line_2d(Vec2::new(-half_width, y), Vec2::new(half_width, y), color);
+}
+
+pub fn update_score_system(mut game: ResMut<Game>) {
+    if game.score - game.interpolated_score >= 10 {
+        game.interpolated_score += 10;
+    } else {
+        game.interpolated_score = game.score;
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn text_update_system(
+    game: ResMut<Game>,
+    rules: Res<GameRules>,
+    mut score_text: Query<&mut Text, With<ScoreText>>,
+    mut timer_text: Query<&mut Text, (With<TimerText>, Without<ScoreText>)>,
+    mut strike_text: Query<&mut Text, (With<StrikeText>, Without<ScoreText>, Without<TimerText>)>,
+    mut combo_text: Query<
+        &mut Text,
+        (
+            With<ComboText>,
+            Without<ScoreText>,
+            Without<TimerText>,
+            Without<StrikeText>,
+        ),
+    >,
+) {
+    for mut text in &mut score_text {
+        let score = game.interpolated_score;
+        text.sections[0].value = format!("{score}");
+    }
+    for mut text in &mut timer_text {
+        text.sections[0].value = if game.mode.time_limit(&rules).is_some() {
+            clock_text(game.time_left.ceil())
+        } else {
+            String::new()
+        };
+    }
+    for mut text in &mut strike_text {
+        let limit = rules.strike_limit;
+        let strikes = limit - game.strikes;
+        text.sections[0].value = if game.mode.counts_strikes(&rules) {
+            format!("{strikes}/{limit}")
+        } else {
+            String::new()
+        };
+    }
+    for mut text in &mut combo_text {
+        let combo = game.combo;
+        text.sections[0].value = if combo > 1 && game.combo_window > 0. {
+            format!("x{combo} combo")
+        } else {
+            String::new()
+        };
+    }
+}
+
+pub fn spawn_game_overlay_system(
+    mut commands: Commands,
+    overlay: Query<Entity, With<GameOverlay>>,
+    rules: Res<GameRules>,
+    asset_server: Res<AssetServer>,
+) {
+    for entity in overlay.iter() {
+        commands.entity(entity).despawn();
+    }
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    // fill the entire window
+                    width: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..Default::default()
+                },
+                background_color: BackgroundColor(Color::Rgba {
+                    red: 0.,
+                    green: 0.,
+                    blue: 0.,
+                    alpha: 0.5,
+                }),
+                ..Default::default()
+            },
+            GameOverlay,
+        ))
+        .with_children(|builder| {
+            builder
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            align_items: AlignItems::Center,
+                            column_gap: Val::Px(30.),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    GameOverlay,
+                ))
+                .with_children(|row| {
+                    row.spawn((
+                        TextBundle::from_section(
+                            "0",
+                            TextStyle {
+                                font: asset_server.load("fonts/kuga.ttf"),
+                                font_size: 80.0,
+                                ..default()
+                            },
+                        )
+                        .with_text_alignment(TextAlignment::Center)
+                        .with_style(Style {
+                            flex_direction: FlexDirection::Column,
+                            align_items: AlignItems::FlexStart,
+                            justify_content: JustifyContent::Center,
+                            top: Val::Px(10.),
+                            ..default()
+                        }),
+                        ScoreText,
+                        GameOverlay,
+                    ));
+                    row.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font: asset_server.load("fonts/kuga.ttf"),
+                                font_size: 50.0,
+                                color: Color::ORANGE,
+                            },
+                        )
+                        .with_style(Style {
+                            top: Val::Px(10.),
+                            ..default()
+                        }),
+                        TimerText,
+                        GameOverlay,
+                    ));
+                });
+            builder.spawn((
+                TextBundle::from_section(
+                    format!("{0}/{0}", rules.strike_limit),
+                    TextStyle {
+                        font: asset_server.load("fonts/kuga.ttf"),
+                        font_size: 30.0,
+                        color: Color::RED,
+                    },
+                )
+                .with_text_alignment(TextAlignment::Center)
+                .with_style(Style {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::FlexStart,
+                    justify_content: JustifyContent::FlexEnd,
+                    ..default()
+                }),
+                StrikeText,
+                GameOverlay,
+            ));
+            builder.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/kuga.ttf"),
+                        font_size: 40.0,
+                        color: Color::GOLD,
+                    },
+                )
+                .with_text_alignment(TextAlignment::Center),
+                ComboText,
+                GameOverlay,
+            ));
+        });
+}
+
+pub fn game_over_system(
+    game: ResMut<Game>,
+    rules: Res<GameRules>,
+    mut game_over_ev: EventReader<GameOverEvent>,
+    high_scores: Option<Res<HighScores>>,
+    mut commands: Commands,
+    overlay: Query<Entity, With<GameOverlay>>,
+    asset_server: Res<AssetServer>,
+) {
+    for entity in overlay.iter() {
+        commands.entity(entity).despawn();
+    }
+    let reason = game_over_ev.read().last().map(|game_over| game_over.reason);
+    let title = match reason {
+        Some(GameOverReason::OutOfTime) => "Time's up!",
+        _ => "Game Over...",
+    };
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    // fill the entire window
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                background_color: BackgroundColor(Color::BLACK),
+                ..Default::default()
+            },
+            GameOverOverlay,
+        ))
+        .with_children(|builder| {
+            builder.spawn((
+                centered_text(title, 100., Color::WHITE, &asset_server),
+                GameOverOverlay,
+            ));
+            let score = game.score;
+            builder.spawn((
+                centered_text(format!("Score: {score}"), 70., Color::WHITE, &asset_server),
+                GameOverOverlay,
+            ));
+            if let Some(high_scores) = high_scores {
+                let mode = game.mode.name();
+                let best = high_scores.best(game.mode);
+                let (text, color) = match high_scores.last_rank {
+                    Some(0) => (format!("New {mode} high score!"), Color::GOLD),
+                    Some(rank) => {
+                        let place = rank + 1;
+                        (
+                            format!("{mode} high score: {best} - you placed #{place}"),
+                            Color::WHITE,
+                        )
+                    }
+                    None => (format!("{mode} high score: {best}"), Color::WHITE),
+                };
+                builder.spawn((
+                    centered_text(text, 40., color, &asset_server),
+                    GameOverOverlay,
+                ));
+            }
+            if let Some(limit) = game.mode.time_limit(&rules) {
+                // How long the clock ran for, merges' bonus time included
+                let played = limit + game.bonus_seconds - game.time_left;
+                let text = if game.bonus_seconds > 0. {
+                    format!(
+                        "Played {} - merges bought you {}",
+                        clock_text(played.round()),
+                        clock_text(game.bonus_seconds.round())
+                    )
+                } else {
+                    format!("Played {}", clock_text(played.round()))
+                };
+                builder.spawn((
+                    centered_text(text, 30., Color::ORANGE, &asset_server),
+                    GameOverOverlay,
+                ));
+            }
+            let max_combo = game.max_combo;
+            if max_combo > 1 {
+                builder.spawn((
+                    centered_text(
+                        format!("Best combo: x{max_combo}"),
+                        30.,
+                        Color::GOLD,
+                        &asset_server,
+                    ),
+                    GameOverOverlay,
+                ));
+            }
+            let final_tiers_reached = game.final_tiers_reached;
+            if final_tiers_reached > 0 {
+                builder.spawn((
+                    centered_text(
+                        format!("Final tier reached: x{final_tiers_reached}"),
+                        30.,
+                        Color::GOLD,
+                        &asset_server,
+                    ),
+                    GameOverOverlay,
+                ));
+            }
+            let final_tiers_cleared = game.final_tiers_cleared;
+            if final_tiers_cleared > 0 {
+                builder.spawn((
+                    centered_text(
+                        format!("Final tier cleared: x{final_tiers_cleared}"),
+                        30.,
+                        Color::GOLD,
+                        &asset_server,
+                    ),
+                    GameOverOverlay,
+                ));
+            }
+            let seed = game.seed;
+            builder.spawn((
+                centered_text(format!("Seed: {seed}"), 30., Color::WHITE, &asset_server),
+                GameOverOverlay,
+            ));
+            builder.spawn((
+                centered_text(
+                    "Click anywhere to restart, R to replay the seed, Esc for the menu",
+                    30.,
+                    Color::WHITE,
+                    &asset_server,
+                ),
+                GameOverOverlay,
+            ));
+        });
+}
+
+/// Whole seconds as minutes and seconds, e.g. `2:05`.
+fn clock_text(seconds: f32) -> String {
+    let seconds = seconds as i32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+pub fn despawn_game_over_system(
+    mut commands: Commands,
+    overlay: Query<Entity, With<GameOverOverlay>>,
+) {
+    for entity in overlay.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Spawns a labelled menu button carrying `marker`, so its handler can tell it apart.
+pub fn spawn_menu_button(
+    builder: &mut ChildBuilder,
+    label: &str,
+    marker: impl Bundle,
+    asset_server: &AssetServer,
+) {
+    builder
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(180.),
+                    height: Val::Px(44.),
+                    margin: UiRect::all(Val::Px(6.)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BackgroundColor(MENU_BUTTON_COLOR),
+                ..default()
+            },
+            MenuButton,
+            marker,
+        ))
+        .with_children(|button| {
+            button.spawn(centered_text(label, 25., Color::WHITE, asset_server));
+        });
+}
+
+#[allow(clippy::type_complexity)]
+pub fn menu_button_color_system(
+    mut buttons: Query<
+        (&Interaction, &mut BackgroundColor),
+        (Changed<Interaction>, With<MenuButton>),
+    >,
+) {
+    for (interaction, mut color) in buttons.iter_mut() {
+        color.0 = match interaction {
+            Interaction::Pressed => MENU_BUTTON_PRESSED_COLOR,
+            Interaction::Hovered => MENU_BUTTON_HOVERED_COLOR,
+            Interaction::None => MENU_BUTTON_COLOR,
+        };
+    }
+}
+
+pub fn centered_text(
+    text: impl Into<String>,
+    font_size: f32,
+    color: Color,
+    asset_server: &AssetServer,
+) -> TextBundle {
+    TextBundle::from_section(
+        text,
+        TextStyle {
+            font: asset_server.load("fonts/kuga.ttf"),
+            font_size,
+            color,
+        },
+    )
+    .with_text_alignment(TextAlignment::Center)
+    .with_style(Style {
+        flex_direction: FlexDirection::Column,
+        align_items: AlignItems::Center,
+        justify_content: JustifyContent::Center,
+        ..default()
+    })
+}
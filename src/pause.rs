@@ -0,0 +1,184 @@
+use bevy::{prelude::*, window::WindowFocused};
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    game::{new_game_system, start_new_game, AppState, NewGame},
+    overlay::{centered_text, spawn_game_overlay_system, spawn_menu_button, GameOverlay},
+    settings::Settings,
+};
+
+/// Pausing with Escape / P, the on-screen pause button or by switching away from the window,
+/// and the pause menu.
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(AppState::Playing),
+            spawn_pause_button_system
+                .after(new_game_system)
+                .after(spawn_game_overlay_system)
+                .run_if(resource_exists::<NewGame>()),
+        )
+        .add_systems(
+            OnEnter(AppState::Paused),
+            (freeze_physics_system, spawn_pause_menu_system),
+        )
+        .add_systems(
+            OnExit(AppState::Paused),
+            (resume_physics_system, despawn_pause_menu_system),
+        )
+        .add_systems(
+            Update,
+            (
+                (pause_button_system, focus_lost_system).run_if(in_state(AppState::Playing)),
+                pause_menu_system.run_if(in_state(AppState::Paused)),
+                pause_key_system
+                    .run_if(in_state(AppState::Playing).or_else(in_state(AppState::Paused))),
+            ),
+        );
+    }
+}
+
+#[derive(Component)]
+pub struct PauseButton;
+
+#[derive(Component)]
+pub struct PauseOverlay;
+
+#[derive(Component, Clone, Copy)]
+pub enum PauseMenuButton {
+    Resume,
+    Restart,
+    Quit,
+}
+
+pub fn spawn_pause_button_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(10.),
+                    left: Val::Px(10.),
+                    width: Val::Px(40.),
+                    height: Val::Px(40.),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.5)),
+                ..default()
+            },
+            PauseButton,
+            GameOverlay,
+        ))
+        .with_children(|button| {
+            button.spawn((
+                centered_text("II", 30., Color::WHITE, &asset_server),
+                GameOverlay,
+            ));
+        });
+}
+
+pub fn pause_key_system(
+    keys: Res<Input<KeyCode>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if keys.any_just_pressed([KeyCode::Escape, KeyCode::P]) {
+        next_state.set(match state.get() {
+            AppState::Paused => AppState::Playing,
+            _ => AppState::Paused,
+        });
+    }
+}
+
+pub fn pause_button_system(
+    buttons: Query<&Interaction, (Changed<Interaction>, With<PauseButton>)>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if buttons
+        .iter()
+        .any(|interaction| *interaction == Interaction::Pressed)
+    {
+        next_state.set(AppState::Paused);
+    }
+}
+
+/// Nobody's watching the bucket while the window is in the background.
+pub fn focus_lost_system(
+    mut focus_ev: EventReader<WindowFocused>,
+    settings: Option<Res<Settings>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let auto_pause = settings.is_none_or(|settings| settings.auto_pause);
+    if focus_ev.read().any(|ev| !ev.focused) && auto_pause {
+        next_state.set(AppState::Paused);
+    }
+}
+
+pub fn freeze_physics_system(mut config: ResMut<RapierConfiguration>) {
+    config.physics_pipeline_active = false;
+}
+
+pub fn resume_physics_system(mut config: ResMut<RapierConfiguration>) {
+    config.physics_pipeline_active = true;
+}
+
+pub fn spawn_pause_menu_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    // fill the entire window
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.7)),
+                ..default()
+            },
+            PauseOverlay,
+        ))
+        .with_children(|builder| {
+            builder.spawn(centered_text("Paused", 80., Color::WHITE, &asset_server));
+            for (label, button) in [
+                ("Resume", PauseMenuButton::Resume),
+                ("Restart", PauseMenuButton::Restart),
+                ("Quit to menu", PauseMenuButton::Quit),
+            ] {
+                spawn_menu_button(builder, label, button, &asset_server);
+            }
+        });
+}
+
+pub fn despawn_pause_menu_system(
+    mut commands: Commands,
+    overlay: Query<Entity, With<PauseOverlay>>,
+) {
+    for entity in overlay.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub fn pause_menu_system(
+    mut commands: Commands,
+    buttons: Query<(&Interaction, &PauseMenuButton), Changed<Interaction>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for (interaction, button) in buttons.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button {
+            PauseMenuButton::Resume => next_state.set(AppState::Playing),
+            PauseMenuButton::Restart => start_new_game(&mut commands, &mut next_state, true),
+            PauseMenuButton::Quit => next_state.set(AppState::MainMenu),
+        }
+    }
+}
@@ -0,0 +1,482 @@
+use bevy::{
+    prelude::*,
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+    utils::{HashMap, HashSet},
+};
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    ball::{BallProgress, BallTarget, BallType, Fuse},
+    game::{AppState, Game, GameMode},
+    rules::GameRules,
+    tiers::BallTiers,
+    BingleSet, WALL_THICKNESS,
+};
+
+/// Bucket walls, out of bounds barriers, contact tracking, merging and growth.
+pub struct MergePhysicsPlugin;
+
+impl Plugin for MergePhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<RapierPhysicsPlugin>() {
+            app.add_plugins(RapierPhysicsPlugin::<()>::default().in_schedule(FixedUpdate));
+        }
+        // Merging runs on the fixed timestep right after each physics step so a game plays
+        // out the same way for the same drops, no matter the frame rate
+        app.init_resource::<GameRules>()
+            .add_event::<FinalTierReachedEvent>()
+            .add_event::<FinalTierClearedEvent>()
+            .init_resource::<Contacts>()
+            .init_resource::<SimulationTick>()
+            .configure_sets(
+                FixedUpdate,
+                BingleSet::Merge
+                    .after(PhysicsSet::Writeback)
+                    .run_if(in_state(AppState::Playing)),
+            )
+            .add_systems(Startup, setup_physics)
+            .add_systems(
+                FixedUpdate,
+                (
+                    collision_system,
+                    squash_balls.after(collision_system),
+                    bomb_system.after(squash_balls),
+                    grow_system.after(bomb_system),
+                    tick_system.after(grow_system),
+                )
+                    .in_set(BingleSet::Merge),
+            );
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Contacts(pub HashSet<(Entity, Entity)>);
+
+/// Number of fixed physics steps since the current game started.
+#[derive(Resource, Default)]
+pub struct SimulationTick(pub u64);
+
+#[derive(Component)]
+pub struct OutOfBoundsBarrier;
+
+/// Sent when a merge starts a ball growing into the final tier.
+#[derive(Event, Clone, Copy)]
+pub struct FinalTierReachedEvent {
+    pub position: Vec2,
+}
+
+/// Sent when two final tier balls merge, clearing each other out of the bucket.
+#[derive(Event, Clone, Copy)]
+pub struct FinalTierClearedEvent {
+    /// Halfway between the two balls.
+    pub position: Vec2,
+    /// Points the clear scored, bonus and combo included.
+    pub score: i32,
+}
+
+pub fn spawn_walls(
+    commands: &mut Commands,
+    walls: &[(f32, f32, f32, f32)],
+    barrier: bool,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<ColorMaterial>>,
+) {
+    for wall in walls.iter() {
+        let width = wall.0;
+        let height = wall.1;
+        let x = wall.2;
+        let y = wall.3;
+
+        if barrier {
+            commands
+                .spawn((
+                    MaterialMesh2dBundle {
+                        mesh: meshes.add(shape::Box::new(width, height, 0.).into()).into(),
+                        material: materials.add(ColorMaterial::from(Color::RED)),
+                        transform: Transform::IDENTITY,
+                        ..default()
+                    },
+                    OutOfBoundsBarrier,
+                ))
+                .insert(Collider::cuboid(width / 2., height / 2.))
+                .insert(TransformBundle::from(Transform::from_xyz(x, y, 0.0)));
+        } else {
+            commands
+                .spawn(MaterialMesh2dBundle {
+                    mesh: meshes.add(shape::Box::new(width, height, 0.).into()).into(),
+                    material: materials.add(ColorMaterial::from(Color::ANTIQUE_WHITE)),
+                    transform: Transform::IDENTITY,
+                    ..default()
+                })
+                .insert(Collider::cuboid(width / 2., height / 2.))
+                .insert(TransformBundle::from(Transform::from_xyz(x, y, 0.0)));
+        }
+    }
+}
+
+pub fn setup_physics(
+    mut commands: Commands,
+    rules: Res<GameRules>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let default_config = RapierConfiguration::default();
+    let config = RapierConfiguration {
+        gravity: default_config.gravity * rules.gravity_scale,
+        timestep_mode: TimestepMode::Fixed {
+            dt: rules.physics_timestep,
+            substeps: rules.physics_substeps,
+        },
+        ..default_config
+    };
+    commands.insert_resource(config);
+    let bucket_width = rules.bucket_width;
+    let bucket_height = rules.bucket_height;
+    let bucket_y_offset = rules.bucket_y_offset();
+    let mut walls = Vec::<(f32, f32, f32, f32)>::new();
+    // Floor
+    walls.push((
+        bucket_width + WALL_THICKNESS,
+        WALL_THICKNESS,
+        0.,
+        -(bucket_height / 2.) + bucket_y_offset,
+    ));
+    // Left wall
+    walls.push((
+        WALL_THICKNESS,
+        bucket_height + WALL_THICKNESS,
+        -(bucket_width / 2.),
+        bucket_y_offset,
+    ));
+    // Right wall
+    walls.push((
+        WALL_THICKNESS,
+        bucket_height + WALL_THICKNESS,
+        bucket_width / 2.,
+        bucket_y_offset,
+    ));
+    spawn_walls(&mut commands, &walls, false, &mut meshes, &mut materials);
+    walls.clear();
+
+    let arena_half_size = rules.arena_half_size();
+    // Left wall
+    walls.push((
+        WALL_THICKNESS,
+        arena_half_size * 2. + WALL_THICKNESS,
+        arena_half_size,
+        0.,
+    ));
+    walls.push((
+        WALL_THICKNESS,
+        arena_half_size * 2. + WALL_THICKNESS,
+        -arena_half_size,
+        0.,
+    ));
+    // Cieling
+    walls.push((
+        arena_half_size * 2. + WALL_THICKNESS,
+        WALL_THICKNESS,
+        0.,
+        arena_half_size,
+    ));
+    // Floor
+    walls.push((
+        arena_half_size * 2. + WALL_THICKNESS,
+        WALL_THICKNESS,
+        0.,
+        -arena_half_size,
+    ));
+    spawn_walls(&mut commands, &walls, true, &mut meshes, &mut materials);
+}
+
+pub fn grow_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    rules: Res<GameRules>,
+    tiers: Res<BallTiers>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut balls_growing: Query<(
+        Entity,
+        &mut BallType,
+        &BallTarget,
+        &mut BallProgress,
+        &mut Collider,
+        &Mesh2dHandle,
+    )>,
+) {
+    for (entity, mut ball_type, target, mut progress, mut collider, mesh) in
+        balls_growing.iter_mut()
+    {
+        progress.0 += time.delta_seconds() / rules.grow_duration_seconds;
+        if progress.0 >= 1. {
+            *ball_type = BallType::Simple(target.0);
+            commands.entity(entity).remove::<BallProgress>();
+            commands.entity(entity).remove::<BallTarget>();
+            // Takes on the new tier's collider & physical properties
+            commands.entity(entity).insert(ball_type.physics(&tiers));
+            if let Some(mesh) = meshes.get_mut(&mesh.0) {
+                *mesh = shape::Circle::new(ball_type.size(&tiers)).into();
+            }
+        } else {
+            let from = ball_type.size(&tiers);
+            let to = BallType::Simple(target.0).size(&tiers);
+            let size = from + ((to - from) * progress.0);
+            *collider = Collider::ball(size);
+            if let Some(mesh) = meshes.get_mut(&mesh.0) {
+                *mesh = shape::Circle::new(size).into();
+            }
+        }
+    }
+}
+
+pub fn collision_system(
+    mut collision_events: EventReader<CollisionEvent>,
+    mut contacts: ResMut<Contacts>,
+) {
+    for collision_event in collision_events.read() {
+        match collision_event {
+            CollisionEvent::Started(entity_a, entity_b, _) => {
+                contacts.0.insert((*entity_a, *entity_b));
+            }
+            CollisionEvent::Stopped(entity_a, entity_b, _) => {
+                contacts.0.remove(&(*entity_a, *entity_b));
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn squash_balls(
+    mut game: ResMut<Game>,
+    time: Res<Time>,
+    rules: Res<GameRules>,
+    tiers: Res<BallTiers>,
+    mut commands: Commands,
+    mut contacts: ResMut<Contacts>,
+    balls: Query<(
+        Entity,
+        &BallType,
+        Option<&BallTarget>,
+        Option<&BallProgress>,
+        &Transform,
+        &Handle<ColorMaterial>,
+    )>,
+    barriers: Query<(Entity, &OutOfBoundsBarrier)>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut reached_ev: EventWriter<FinalTierReachedEvent>,
+    mut cleared_ev: EventWriter<FinalTierClearedEvent>,
+) {
+    let mut ball_types = HashMap::<Entity, (BallType, Transform)>::new();
+    for (entity, ball_type, ball_target, _, transform, _) in balls.iter() {
+        let match_type;
+        if let Some(target) = ball_target {
+            match_type = BallType::Simple(target.0);
+        } else {
+            match_type = *ball_type;
+        }
+        ball_types.insert(entity, (match_type, *transform));
+    }
+    let mut barrier_entities = HashMap::<Entity, bool>::new();
+    for (entity, _) in barriers.iter() {
+        barrier_entities.insert(entity, true);
+    }
+    game.combo_window = (game.combo_window - time.delta_seconds()).max(0.);
+    let mut to_remove = HashSet::<(Entity, Entity)>::new();
+    // Each ball takes part in at most one merge or strike per step
+    let mut visited = HashSet::<Entity>::new();
+    // Visit contacts in a stable order so the same game always merges the same way
+    let mut sorted_contacts = contacts.0.iter().collect::<Vec<_>>();
+    sorted_contacts.sort();
+    for contact in sorted_contacts {
+        // The ball that stays & grows, the ball that's absorbed, the level it grows to (none when
+        // both are cleared out) and what the merged balls are worth
+        let merge = match (ball_types.get(&contact.0), ball_types.get(&contact.1)) {
+            (
+                Some((BallType::Simple(level_a), transform_a)),
+                Some((BallType::Simple(level_b), transform_b)),
+            ) => {
+                let matches = match game.mode {
+                    GameMode::Colors => tiers.tier(*level_a).color == tiers.tier(*level_b).color,
+                    _ => level_a == level_b,
+                };
+                let merged_score = tiers.tier(*level_a).score + tiers.tier(*level_b).score;
+                if matches && tiers.is_final(*level_a) && tiers.is_final(*level_b) {
+                    Some((
+                        contact.0,
+                        contact.1,
+                        None,
+                        merged_score + rules.final_tier_bonus,
+                    ))
+                } else if matches && !tiers.is_final(*level_a.max(level_b)) {
+                    let lower = f32::min(transform_a.translation.y, transform_b.translation.y);
+                    let a_lower = lower == transform_a.translation.y;
+                    let replaced = if a_lower { contact.0 } else { contact.1 };
+                    let removed = if a_lower { contact.1 } else { contact.0 };
+                    Some((
+                        replaced,
+                        removed,
+                        Some(level_a.max(level_b) + 1),
+                        merged_score,
+                    ))
+                } else {
+                    None
+                }
+            }
+            // Wildcards merge with the first simple ball they touch, whatever its level
+            (Some((BallType::Simple(level), _)), Some((BallType::Special, _)))
+                if !tiers.is_final(*level) =>
+            {
+                Some((
+                    contact.0,
+                    contact.1,
+                    Some(level + 1),
+                    tiers.tier(*level).score * 2,
+                ))
+            }
+            (Some((BallType::Special, _)), Some((BallType::Simple(level), _)))
+                if !tiers.is_final(*level) =>
+            {
+                Some((
+                    contact.1,
+                    contact.0,
+                    Some(level + 1),
+                    tiers.tier(*level).score * 2,
+                ))
+            }
+            _ => None,
+        };
+        match merge {
+            Some((replaced, removed, upgraded_level, merged_score)) => {
+                if !visited.contains(&removed) && visited.insert(replaced) {
+                    visited.insert(removed);
+                    // Merging a ball that's still growing, or soon after the last merge,
+                    // carries the combo on
+                    let growing = [replaced, removed]
+                        .iter()
+                        .any(|entity| balls.get(*entity).is_ok_and(|ball| ball.3.is_some()));
+                    game.combo = if growing || game.combo_window > 0. {
+                        game.combo.max(1) + 1
+                    } else {
+                        1
+                    };
+                    game.combo_window = rules.combo_window_seconds;
+                    game.max_combo = game.max_combo.max(game.combo);
+                    commands.entity(removed).despawn();
+                    let score = merged_score * game.combo;
+                    game.score += score;
+                    let bonus_seconds = game.mode.merge_bonus_seconds(&rules);
+                    game.time_left += bonus_seconds;
+                    game.bonus_seconds += bonus_seconds;
+                    let Some(upgraded_level) = upgraded_level else {
+                        // Two final tier balls have nowhere to go but out
+                        commands.entity(replaced).despawn();
+                        to_remove.insert(*contact);
+                        let position = (ball_types[&replaced].1.translation
+                            + ball_types[&removed].1.translation)
+                            .truncate()
+                            * 0.5;
+                        cleared_ev.send(FinalTierClearedEvent { position, score });
+                        continue;
+                    };
+                    if tiers.is_final(upgraded_level) {
+                        reached_ev.send(FinalTierReachedEvent {
+                            position: ball_types[&replaced].1.translation.truncate(),
+                        });
+                    }
+                    if let Some(replaced_ball) = balls.iter().find(|ball| ball.0 == replaced) {
+                        // Update existing entity's color & add components for growth
+                        let upgraded_ball_type = BallType::Simple(upgraded_level);
+                        materials.insert(replaced_ball.5, upgraded_ball_type.color(&tiers));
+                        commands.entity(replaced).insert(BallTarget(upgraded_level));
+                        if let Some(current_progress) = replaced_ball.3 {
+                            commands
+                                .entity(replaced)
+                                .insert(BallProgress(current_progress.0 * 0.5));
+                        } else {
+                            commands.entity(replaced).insert(BallProgress(0.));
+                        }
+                        to_remove.insert(*contact);
+                    }
+                    game.max_level = game.max_level.max(upgraded_level);
+                }
+            }
+            None => {
+                let mut hit_barrier = false;
+                if barrier_entities.get(&contact.0) == Some(&true) && visited.insert(contact.1) {
+                    commands.get_entity(contact.1).unwrap();
+                    commands.entity(contact.1).despawn();
+                    hit_barrier = true;
+                } else if barrier_entities.get(&contact.1) == Some(&true)
+                    && visited.insert(contact.0)
+                {
+                    commands.get_entity(contact.0).unwrap();
+                    commands.entity(contact.0).despawn();
+                    hit_barrier = true;
+                }
+                if hit_barrier {
+                    to_remove.insert(*contact);
+                    if game.mode.counts_strikes(&rules) {
+                        game.strikes += 1;
+                    }
+                }
+            }
+        }
+    }
+    for despawned in to_remove {
+        contacts.0.remove(&despawned);
+    }
+}
+
+/// Lights a bomb's fuse once it lands on something, then clears every ball around it when the
+/// fuse runs out.
+#[allow(clippy::too_many_arguments)]
+pub fn bomb_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut game: ResMut<Game>,
+    mut contacts: ResMut<Contacts>,
+    rules: Res<GameRules>,
+    tiers: Res<BallTiers>,
+    mut bombs: Query<(Entity, &BallType, Option<&mut Fuse>, &Transform)>,
+    balls: Query<(Entity, &BallType, &Transform)>,
+    barriers: Query<(), With<OutOfBoundsBarrier>>,
+) {
+    let mut cleared = HashSet::<Entity>::new();
+    for (bomb, ball_type, fuse, transform) in bombs.iter_mut() {
+        if *ball_type != BallType::Bomb || cleared.contains(&bomb) {
+            continue;
+        }
+        let Some(mut fuse) = fuse else {
+            let landed = contacts.0.iter().any(|(a, b)| {
+                (*a == bomb && !barriers.contains(*b)) || (*b == bomb && !barriers.contains(*a))
+            });
+            if landed {
+                commands.entity(bomb).insert(Fuse(0.));
+            }
+            continue;
+        };
+        fuse.0 += time.delta_seconds() / rules.bomb_fuse_seconds;
+        if fuse.0 < 1. {
+            continue;
+        }
+        for (entity, other_type, other_transform) in balls.iter() {
+            let distance = transform
+                .translation
+                .truncate()
+                .distance(other_transform.translation.truncate());
+            if distance <= rules.bomb_radius + other_type.size(&tiers) && cleared.insert(entity) {
+                commands.entity(entity).despawn();
+                if entity != bomb {
+                    game.score += rules.bomb_score_per_ball;
+                }
+            }
+        }
+    }
+    contacts
+        .0
+        .retain(|(a, b)| !cleared.contains(a) && !cleared.contains(b));
+}
+
+pub fn tick_system(mut tick: ResMut<SimulationTick>) {
+    tick.0 += 1;
+}
@@ -0,0 +1,370 @@
+use bevy::{app::AppExit, prelude::*};
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    ball::{spawn_ball, BallType},
+    game::{new_game_system, AppState, BallDroppedEvent, Game, GameMode, NewGame},
+    physics::SimulationTick,
+    rules::GameRules,
+    savegame::resume_game_system,
+    tiers::BallTiers,
+    BingleSet,
+};
+
+/// Every drop of a single game, enough to play it back exactly.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Replay {
+    pub seed: u64,
+    #[serde(default)]
+    pub mode: GameMode,
+    /// Rules the game was played by. Play it back by the same ones to get the same game.
+    #[serde(default)]
+    pub rules: GameRules,
+    /// `BallTiers::fingerprint` of the tiers the game was played with, when known.
+    #[serde(default)]
+    pub tiers_fingerprint: Option<u64>,
+    pub drops: Vec<ReplayDrop>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct ReplayDrop {
+    /// Fixed physics step the ball was dropped after, counted from the start of the game.
+    pub tick: u64,
+    pub x: f32,
+    pub ball_type: BallType,
+    /// Whether the hold was used since the previous drop, to keep the dropper in step.
+    #[serde(default)]
+    pub held: bool,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        ron::from_str(&contents).map_err(|e| e.to_string())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| e.to_string())?;
+        std::fs::write(path, contents).map_err(|e| e.to_string())
+    }
+
+    fn last_tick(&self) -> u64 {
+        self.drops.last().map_or(0, |drop| drop.tick)
+    }
+}
+
+/// Records every drop of the current game and writes it to `path` on game over and on exit.
+/// Resumed games aren't recorded, a replay can only play back from an empty bucket.
+pub struct ReplayRecordPlugin {
+    pub path: PathBuf,
+}
+
+impl Plugin for ReplayRecordPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ReplayRecorder {
+            path: self.path.clone(),
+            replay: None,
+        })
+        .add_systems(
+            OnEnter(AppState::Playing),
+            start_recording_system
+                .after(new_game_system)
+                .after(resume_game_system)
+                .run_if(resource_exists::<NewGame>()),
+        )
+        .add_systems(Update, record_drops_system.after(BingleSet::Input))
+        .add_systems(OnEnter(AppState::GameOver), save_replay_system)
+        .add_systems(Last, save_replay_on_exit_system);
+    }
+}
+
+/// Re-injects the drops of a replay at the fixed step they were recorded at.
+pub struct ReplayPlaybackPlugin {
+    pub replay: Replay,
+}
+
+impl Plugin for ReplayPlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ReplayPlayback {
+            replay: self.replay.clone(),
+            next: 0,
+        })
+        .add_systems(
+            OnEnter(AppState::Playing),
+            check_replay_tiers_system
+                .after(new_game_system)
+                .run_if(resource_exists::<NewGame>()),
+        )
+        .configure_sets(
+            FixedUpdate,
+            BingleSet::Replay
+                .before(PhysicsSet::SyncBackend)
+                .run_if(in_state(AppState::Playing)),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                replay_drops_system.in_set(BingleSet::Replay),
+                // Rapier only flushes commands after it has picked up new bodies, so spawn the
+                // replayed balls first to have them simulated from the same step as recorded
+                apply_deferred
+                    .after(BingleSet::Replay)
+                    .before(PhysicsSet::SyncBackend),
+            ),
+        );
+    }
+}
+
+/// Plays back a replay with a timeline and pause / step controls instead of mouse or touch input.
+pub struct ReplayViewerPlugin {
+    pub replay: Replay,
+}
+
+impl Plugin for ReplayViewerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ReplayPlaybackPlugin {
+            replay: self.replay.clone(),
+        })
+        .add_systems(Startup, setup_timeline)
+        .add_systems(
+            Update,
+            (
+                replay_controls_system,
+                step_replay_system
+                    .run_if(step_requested)
+                    .after(replay_controls_system),
+                timeline_update_system,
+            ),
+        );
+    }
+}
+
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    pub path: PathBuf,
+    /// The game being recorded, if it can be.
+    pub replay: Option<Replay>,
+}
+
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    pub next: usize,
+}
+
+impl ReplayPlayback {
+    pub fn finished(&self) -> bool {
+        self.next >= self.replay.drops.len()
+    }
+}
+
+#[derive(Component)]
+pub struct ReplayTimelineFill;
+
+#[derive(Component)]
+pub struct ReplayTimelineText;
+
+pub fn start_recording_system(
+    game: Res<Game>,
+    rules: Res<GameRules>,
+    tiers: Res<BallTiers>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    if game.resumed {
+        warn!("Not recording a resumed game, it can't be played back from its seed");
+        recorder.replay = None;
+        return;
+    }
+    recorder.replay = Some(Replay {
+        seed: game.seed,
+        mode: game.mode,
+        rules: rules.clone(),
+        tiers_fingerprint: Some(tiers.fingerprint()),
+        drops: Vec::new(),
+    });
+}
+
+pub fn record_drops_system(
+    tick: Res<SimulationTick>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut dropped_ev: EventReader<BallDroppedEvent>,
+) {
+    let Some(replay) = &mut recorder.replay else {
+        return;
+    };
+    for dropped in dropped_ev.read() {
+        replay.drops.push(ReplayDrop {
+            tick: tick.0,
+            x: dropped.x,
+            ball_type: dropped.ball_type,
+            held: dropped.held,
+        });
+    }
+}
+
+pub fn save_replay_system(recorder: Res<ReplayRecorder>) {
+    save_recording(&recorder);
+}
+
+pub fn save_replay_on_exit_system(recorder: Res<ReplayRecorder>, exit_ev: EventReader<AppExit>) {
+    if !exit_ev.is_empty() {
+        save_recording(&recorder);
+    }
+}
+
+fn save_recording(recorder: &ReplayRecorder) {
+    let Some(replay) = &recorder.replay else {
+        return;
+    };
+    if let Err(error) = replay.save(&recorder.path) {
+        error!("Couldn't save replay to {:?}: {error}", recorder.path);
+    }
+}
+
+/// The tiers come from an asset rather than the replay, so a replay recorded before they were
+/// edited won't play back the same.
+pub fn check_replay_tiers_system(playback: Res<ReplayPlayback>, tiers: Res<BallTiers>) {
+    if playback
+        .replay
+        .tiers_fingerprint
+        .is_some_and(|fingerprint| fingerprint != tiers.fingerprint())
+    {
+        warn!("The replay was recorded with different ball tiers, it won't play back the same");
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn replay_drops_system(
+    mut commands: Commands,
+    tick: Res<SimulationTick>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut game: ResMut<Game>,
+    rules: Res<GameRules>,
+    tiers: Res<BallTiers>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut dropped_ev: EventWriter<BallDroppedEvent>,
+) {
+    while let Some(drop) = playback.replay.drops.get(playback.next).copied() {
+        if drop.tick > tick.0 {
+            break;
+        }
+        if drop.held {
+            game.dropper
+                .hold(&tiers, &mut commands, &mut meshes, &mut materials);
+        }
+        spawn_ball(
+            &mut commands,
+            &tiers,
+            drop.ball_type,
+            None,
+            Transform::from_xyz(drop.x, rules.dropper_y(), 0.0),
+            &mut meshes,
+            &mut materials,
+        );
+        game.dropper
+            .advance(&tiers, &mut commands, &mut meshes, &mut materials);
+        dropped_ev.send(BallDroppedEvent {
+            x: drop.x,
+            ball_type: drop.ball_type,
+            held: drop.held,
+        });
+        playback.next += 1;
+    }
+}
+
+fn setup_timeline(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(0.),
+                width: Val::Percent(100.),
+                height: Val::Px(30.),
+                ..default()
+            },
+            background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.5)),
+            ..default()
+        })
+        .with_children(|builder| {
+            builder.spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        width: Val::Percent(0.),
+                        height: Val::Percent(100.),
+                        ..default()
+                    },
+                    background_color: BackgroundColor(Color::SEA_GREEN),
+                    ..default()
+                },
+                ReplayTimelineFill,
+            ));
+            builder.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/kuga.ttf"),
+                        font_size: 20.0,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::all(Val::Px(5.)),
+                    ..default()
+                }),
+                ReplayTimelineText,
+            ));
+        });
+}
+
+/// Space pauses / resumes playback.
+pub fn replay_controls_system(keys: Res<Input<KeyCode>>, mut time: ResMut<Time<Virtual>>) {
+    if keys.just_pressed(KeyCode::Space) {
+        if time.is_paused() {
+            time.unpause();
+        } else {
+            time.pause();
+        }
+    }
+}
+
+/// The right arrow advances a paused replay by one physics step.
+pub fn step_requested(keys: Res<Input<KeyCode>>, time: Res<Time<Virtual>>) -> bool {
+    time.is_paused() && keys.just_pressed(KeyCode::Right)
+}
+
+pub fn step_replay_system(world: &mut World) {
+    // Run a single fixed step the same way the fixed update loop would
+    *world.resource_mut::<Time>() = world.resource::<Time<Fixed>>().as_generic();
+    world.run_schedule(FixedUpdate);
+    *world.resource_mut::<Time>() = world.resource::<Time<Virtual>>().as_generic();
+}
+
+pub fn timeline_update_system(
+    playback: Res<ReplayPlayback>,
+    tick: Res<SimulationTick>,
+    time: Res<Time<Virtual>>,
+    mut fill: Query<&mut Style, With<ReplayTimelineFill>>,
+    mut text: Query<&mut Text, With<ReplayTimelineText>>,
+) {
+    let last_tick = playback.replay.last_tick().max(1);
+    let progress = (tick.0 as f32 / last_tick as f32).min(1.);
+    for mut style in &mut fill {
+        style.width = Val::Percent(progress * 100.);
+    }
+    let current = tick.0;
+    let status = if time.is_paused() {
+        "paused - space to play, right to step"
+    } else if playback.finished() {
+        "finished"
+    } else {
+        "playing - space to pause"
+    };
+    for mut text in &mut text {
+        text.sections[0].value = format!("{current} / {last_tick}   {status}");
+    }
+}
@@ -0,0 +1,350 @@
+//! Tunable rules of the game: the bucket, physics, strikes, timers and special balls.
+//!
+//! Every field can be set from a RON file with `--rules file`, where missing fields keep their
+//! defaults, and then overridden one at a time with `--rule name=value`.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{path::Path, str::FromStr};
+
+use crate::{game::GameMode, spawn::SpawnDistribution, BARRIER_PADDING, UNIVERSAL_SCALE};
+
+/// How far the bucket's floor sits above the bottom barrier.
+const BUCKET_FLOOR_GAP: f32 = 75. * UNIVERSAL_SCALE;
+/// How far below the ceiling barrier balls are dropped from.
+const DROPPER_CEILING_GAP: f32 = 60. * UNIVERSAL_SCALE;
+/// How far below the upcoming ball a held ball waits.
+const HELD_BALL_OFFSET: f32 = 80. * UNIVERSAL_SCALE;
+/// How far above the upcoming ball the first queued ball is shown.
+const QUEUE_PREVIEW_OFFSET: f32 = 55. * UNIVERSAL_SCALE;
+
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct GameRules {
+    pub bucket_width: f32,
+    pub bucket_height: f32,
+    /// Multiplies the pull of gravity on every ball.
+    pub gravity_scale: f32,
+    /// Length of each physics step, in seconds.
+    pub physics_timestep: f32,
+    pub physics_substeps: usize,
+    /// Modes lost by balls going over the bucket's edge `strike_limit` times.
+    pub strike_modes: Vec<GameMode>,
+    /// Balls lost over the bucket's edge before the game is over.
+    pub strike_limit: i32,
+    /// Modes lost by a settled ball staying above the danger line for `overflow_grace_seconds`.
+    pub overflow_modes: Vec<GameMode>,
+    /// How far above the bucket's rim the danger line is.
+    pub danger_line_height: f32,
+    pub overflow_grace_seconds: f32,
+    /// How long a merged ball takes to grow to its new size.
+    pub grow_duration_seconds: f32,
+    /// Length of a `GameMode::Timed` game.
+    pub timed_mode_seconds: f32,
+    /// Length of a `GameMode::Blitz` game, before merges add to it.
+    pub blitz_mode_seconds: f32,
+    pub blitz_merge_bonus_seconds: f32,
+    /// A merge this soon after the last one carries the combo on.
+    pub combo_window_seconds: f32,
+    /// How the dropper picks the level of each simple ball.
+    pub spawn_distribution: SpawnDistribution,
+    /// Chance that the dropper hands out a wildcard `BallType::Special` instead of a simple ball.
+    pub special_ball_chance: f64,
+    /// Chance that the dropper hands out a `BallType::Bomb`.
+    pub bomb_ball_chance: f64,
+    /// Points for merging two final tier balls, on top of what the balls are worth.
+    pub final_tier_bonus: i32,
+    /// How long a bomb's fuse burns after it lands before it goes off.
+    pub bomb_fuse_seconds: f32,
+    /// Balls whose edge is within this distance of a bomb's centre are cleared when it goes off.
+    pub bomb_radius: f32,
+    pub bomb_score_per_ball: i32,
+    /// How long after a drop the dropper is ready for the next one.
+    pub drop_cooldown_seconds: f32,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        GameRules {
+            bucket_width: 300. * UNIVERSAL_SCALE,
+            bucket_height: 150. * UNIVERSAL_SCALE,
+            gravity_scale: 4.,
+            physics_timestep: 0.03,
+            physics_substeps: 2,
+            strike_modes: vec![
+                GameMode::Classic,
+                GameMode::Colors,
+                GameMode::Timed,
+                GameMode::Daily,
+            ],
+            strike_limit: 4,
+            overflow_modes: vec![GameMode::Classic, GameMode::Daily],
+            danger_line_height: 100. * UNIVERSAL_SCALE,
+            overflow_grace_seconds: 3.,
+            grow_duration_seconds: 2.,
+            timed_mode_seconds: 120.,
+            blitz_mode_seconds: 120.,
+            blitz_merge_bonus_seconds: 1.,
+            combo_window_seconds: 0.75,
+            spawn_distribution: SpawnDistribution::default(),
+            special_ball_chance: 0.05,
+            bomb_ball_chance: 0.02,
+            final_tier_bonus: 1000,
+            bomb_fuse_seconds: 1.5,
+            bomb_radius: 45. * UNIVERSAL_SCALE,
+            bomb_score_per_ball: 15,
+            drop_cooldown_seconds: 0.35,
+        }
+    }
+}
+
+impl GameRules {
+    pub fn load(path: &Path) -> Result<GameRules, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let rules: GameRules = ron::from_str(&contents).map_err(|e| e.to_string())?;
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    /// Overrides a single rule by its field name, as given on the command line. The rules are
+    /// left as they were when the value doesn't parse or is out of range.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        fn parse<T: FromStr>(value: &str) -> Result<T, String> {
+            value.parse().map_err(|_| format!("bad value {value}"))
+        }
+        // A comma separated list of mode names, or nothing for none
+        fn parse_modes(value: &str) -> Result<Vec<GameMode>, String> {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| GameMode::from_name(name).ok_or(format!("unknown mode {name}")))
+                .collect()
+        }
+        let mut rules = self.clone();
+        match name {
+            "bucket_width" => rules.bucket_width = parse(value)?,
+            "bucket_height" => rules.bucket_height = parse(value)?,
+            "gravity_scale" => rules.gravity_scale = parse(value)?,
+            "physics_timestep" => rules.physics_timestep = parse(value)?,
+            "physics_substeps" => rules.physics_substeps = parse(value)?,
+            "strike_modes" => rules.strike_modes = parse_modes(value)?,
+            "strike_limit" => rules.strike_limit = parse(value)?,
+            "overflow_modes" => rules.overflow_modes = parse_modes(value)?,
+            "danger_line_height" => rules.danger_line_height = parse(value)?,
+            "overflow_grace_seconds" => rules.overflow_grace_seconds = parse(value)?,
+            "grow_duration_seconds" => rules.grow_duration_seconds = parse(value)?,
+            "timed_mode_seconds" => rules.timed_mode_seconds = parse(value)?,
+            "blitz_mode_seconds" => rules.blitz_mode_seconds = parse(value)?,
+            "blitz_merge_bonus_seconds" => rules.blitz_merge_bonus_seconds = parse(value)?,
+            "combo_window_seconds" => rules.combo_window_seconds = parse(value)?,
+            "spawn_distribution" => {
+                rules.spawn_distribution = ron::from_str(value).map_err(|e| e.to_string())?
+            }
+            "special_ball_chance" => rules.special_ball_chance = parse(value)?,
+            "bomb_ball_chance" => rules.bomb_ball_chance = parse(value)?,
+            "final_tier_bonus" => rules.final_tier_bonus = parse(value)?,
+            "bomb_fuse_seconds" => rules.bomb_fuse_seconds = parse(value)?,
+            "bomb_radius" => rules.bomb_radius = parse(value)?,
+            "bomb_score_per_ball" => rules.bomb_score_per_ball = parse(value)?,
+            "drop_cooldown_seconds" => rules.drop_cooldown_seconds = parse(value)?,
+            _ => return Err(format!("unknown rule {name}")),
+        }
+        rules.validate()?;
+        *self = rules;
+        Ok(())
+    }
+
+    /// Catches values that parse but would break the game, e.g. a chance above 1.
+    pub fn validate(&self) -> Result<(), String> {
+        let chances = [
+            ("special_ball_chance", self.special_ball_chance),
+            ("bomb_ball_chance", self.bomb_ball_chance),
+        ];
+        if let Some((name, _)) = chances
+            .iter()
+            .find(|(_, chance)| !(0. ..=1.).contains(chance))
+        {
+            return Err(format!("{name} must be between 0 and 1"));
+        }
+        let positive = [
+            ("bucket_width", self.bucket_width),
+            ("bucket_height", self.bucket_height),
+            ("physics_timestep", self.physics_timestep),
+            ("grow_duration_seconds", self.grow_duration_seconds),
+            ("timed_mode_seconds", self.timed_mode_seconds),
+            ("blitz_mode_seconds", self.blitz_mode_seconds),
+        ];
+        if let Some((name, _)) = positive
+            .iter()
+            .find(|(_, value)| value.is_nan() || *value <= 0.)
+        {
+            return Err(format!("{name} must be above 0"));
+        }
+        let not_negative = [
+            ("danger_line_height", self.danger_line_height),
+            ("overflow_grace_seconds", self.overflow_grace_seconds),
+            ("blitz_merge_bonus_seconds", self.blitz_merge_bonus_seconds),
+            ("combo_window_seconds", self.combo_window_seconds),
+            ("bomb_fuse_seconds", self.bomb_fuse_seconds),
+            ("bomb_radius", self.bomb_radius),
+            ("drop_cooldown_seconds", self.drop_cooldown_seconds),
+        ];
+        if let Some((name, _)) = not_negative
+            .iter()
+            .find(|(_, value)| value.is_nan() || *value < 0.)
+        {
+            return Err(format!("{name} can't be below 0"));
+        }
+        if self.physics_substeps < 1 {
+            return Err("physics_substeps must be at least 1".into());
+        }
+        if self.strike_limit < 1 {
+            return Err("strike_limit must be at least 1".into());
+        }
+        if let SpawnDistribution::Adaptive {
+            min_levels,
+            max_levels,
+            ..
+        } = self.spawn_distribution
+        {
+            if min_levels > max_levels {
+                return Err("adaptive min_levels can't be above max_levels".into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Half the width and height of the square play area, out to the barriers.
+    pub fn arena_half_size(&self) -> f32 {
+        self.bucket_width.max(self.bucket_height) / 2. + BARRIER_PADDING
+    }
+
+    /// Height of the middle of the bucket.
+    pub fn bucket_y_offset(&self) -> f32 {
+        -self.arena_half_size() + BUCKET_FLOOR_GAP + self.bucket_height / 2.
+    }
+
+    /// Height of the danger line balls mustn't settle above in `overflow_modes`.
+    pub fn danger_line_y(&self) -> f32 {
+        self.bucket_y_offset() + self.bucket_height / 2. + self.danger_line_height
+    }
+
+    /// Height balls are dropped from.
+    pub fn dropper_y(&self) -> f32 {
+        self.arena_half_size() - DROPPER_CEILING_GAP
+    }
+
+    /// Keeps a drop within the bucket plus half the padding either side.
+    pub fn clamp_drop_x(&self, x: f32) -> f32 {
+        let limit = self.bucket_width * 0.5 + BARRIER_PADDING * 0.5;
+        x.clamp(-limit, limit)
+    }
+
+    /// Where the upcoming ball waits, in the padding left of the bucket.
+    pub fn upcoming_ball_position(&self) -> Vec3 {
+        Vec3::new(self.clamp_drop_x(f32::NEG_INFINITY), 0., 0.)
+    }
+
+    /// Where a ball put aside with a hold waits, below the upcoming ball.
+    pub fn held_ball_position(&self) -> Vec3 {
+        self.upcoming_ball_position() - Vec3::Y * HELD_BALL_OFFSET
+    }
+
+    /// Where the first of the queued balls is shown, with the rest stacked above it.
+    pub fn queue_preview_position(&self) -> Vec3 {
+        self.upcoming_ball_position() + Vec3::Y * QUEUE_PREVIEW_OFFSET
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_overrides_a_rule() {
+        let mut rules = GameRules::default();
+        rules.set("strike_limit", "7").unwrap();
+        rules.set("gravity_scale", "2.5").unwrap();
+        rules
+            .set(
+                "spawn_distribution",
+                "Adaptive(min_levels: 2, max_levels: 4, below_top: 1)",
+            )
+            .unwrap();
+        assert_eq!(rules.strike_limit, 7);
+        assert_eq!(rules.gravity_scale, 2.5);
+        assert_eq!(
+            rules.spawn_distribution,
+            SpawnDistribution::Adaptive {
+                min_levels: 2,
+                max_levels: 4,
+                below_top: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn set_rejects_unknown_names_and_bad_values() {
+        let mut rules = GameRules::default();
+        assert_eq!(
+            rules.set("strike_limt", "7"),
+            Err("unknown rule strike_limt".into())
+        );
+        assert_eq!(
+            rules.set("strike_limit", "seven"),
+            Err("bad value seven".into())
+        );
+        assert!(rules.set("physics_substeps", "-1").is_err());
+        assert!(rules.set("spawn_distribution", "Shuffled").is_err());
+        assert_eq!(rules, GameRules::default());
+    }
+
+    #[test]
+    fn set_rejects_out_of_range_values() {
+        let mut rules = GameRules::default();
+        assert_eq!(
+            rules.set("bomb_ball_chance", "5"),
+            Err("bomb_ball_chance must be between 0 and 1".into())
+        );
+        assert_eq!(
+            rules.set("special_ball_chance", "-0.1"),
+            Err("special_ball_chance must be between 0 and 1".into())
+        );
+        assert!(rules.set("bucket_width", "-400").is_err());
+        assert!(rules.set("physics_timestep", "NaN").is_err());
+        assert!(rules.set("physics_substeps", "0").is_err());
+        assert!(rules.set("strike_limit", "0").is_err());
+        assert!(rules
+            .set(
+                "spawn_distribution",
+                "Adaptive(min_levels: 4, max_levels: 2, below_top: 1)"
+            )
+            .is_err());
+        assert_eq!(rules, GameRules::default());
+        rules.set("bomb_ball_chance", "1").unwrap();
+        assert_eq!(rules.bomb_ball_chance, 1.);
+    }
+
+    #[test]
+    fn default_rules_are_valid() {
+        assert_eq!(GameRules::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn set_parses_mode_lists() {
+        let mut rules = GameRules::default();
+        rules.set("overflow_modes", "zen, blitz,classic").unwrap();
+        assert_eq!(
+            rules.overflow_modes,
+            [GameMode::Zen, GameMode::Blitz, GameMode::Classic]
+        );
+        rules.set("strike_modes", "").unwrap();
+        assert!(rules.strike_modes.is_empty());
+        assert_eq!(
+            rules.set("strike_modes", "classic,arcade"),
+            Err("unknown mode arcade".into())
+        );
+        assert!(rules.strike_modes.is_empty());
+    }
+}
@@ -0,0 +1,299 @@
+use bevy::{app::AppExit, prelude::*};
+use bevy_rapier2d::prelude::*;
+use bevy_turborand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ball::{spawn_ball, BallProgress, BallTarget, BallType, Fuse},
+    game::{new_game_system, AppState, Game, GameMode, GameOptions},
+    storage,
+    tiers::BallTiers,
+};
+
+const SAVE_STORAGE_NAME: &str = "savegame";
+const AUTOSAVE_SECONDS: f32 = 10.;
+
+/// Writes the game in progress to storage on exit and picks it back up on the next launch.
+pub struct SaveGamePlugin;
+
+impl Plugin for SaveGamePlugin {
+    fn build(&self, app: &mut App) {
+        // Asking for a particular seed or another mode means starting a fresh game. With the
+        // main menu up the player gets to choose
+        let options = app
+            .world
+            .get_resource::<GameOptions>()
+            .cloned()
+            .unwrap_or_default();
+        if options.seed.is_none() {
+            if let Some(snapshot) =
+                Snapshot::load().filter(|save| options.show_menu || save.mode == options.mode)
+            {
+                app.insert_resource(PendingResume(snapshot));
+            }
+        }
+        app.insert_resource(Autosave(Timer::from_seconds(
+            AUTOSAVE_SECONDS,
+            TimerMode::Repeating,
+        )))
+        .add_systems(
+            OnEnter(AppState::Playing),
+            resume_game_system
+                .after(new_game_system)
+                .run_if(resource_exists::<PendingResume>()),
+        )
+        .add_systems(Update, autosave_system.run_if(in_state(AppState::Playing)))
+        .add_systems(OnEnter(AppState::GameOver), clear_save_system)
+        .add_systems(
+            OnTransition {
+                from: AppState::Paused,
+                to: AppState::MainMenu,
+            },
+            save_on_quit_system,
+        )
+        .add_systems(Last, save_on_exit_system);
+    }
+}
+
+/// Everything needed to put a game in progress back the way it was.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub seed: u64,
+    #[serde(default)]
+    pub mode: GameMode,
+    pub score: i32,
+    pub strikes: i32,
+    pub max_level: i32,
+    #[serde(default)]
+    pub max_combo: i32,
+    #[serde(default)]
+    pub final_tiers_reached: i32,
+    #[serde(default)]
+    pub final_tiers_cleared: i32,
+    #[serde(default)]
+    pub time_left: f32,
+    #[serde(default)]
+    pub bonus_seconds: f32,
+    #[serde(default)]
+    pub overflow_time: f32,
+    /// `BallTiers::fingerprint` of the tiers the game was played with, when known.
+    #[serde(default)]
+    pub tiers_fingerprint: Option<u64>,
+    pub next_ball: BallType,
+    #[serde(default)]
+    pub held: Option<BallType>,
+    #[serde(default)]
+    pub hold_used: bool,
+    #[serde(default)]
+    pub queue: Vec<BallType>,
+    /// What's left in the bag of the `SpawnDistribution::Bag` distribution.
+    #[serde(default)]
+    pub bag: Vec<i32>,
+    pub rng: RngComponent,
+    pub balls: Vec<BallSnapshot>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BallSnapshot {
+    pub ball_type: BallType,
+    pub target: Option<i32>,
+    pub progress: Option<f32>,
+    #[serde(default)]
+    pub fuse: Option<f32>,
+    pub position: [f32; 2],
+    pub rotation: f32,
+    pub linvel: [f32; 2],
+    pub angvel: f32,
+}
+
+/// A snapshot loaded at launch, waiting to be restored once the first game has been set up.
+#[derive(Resource)]
+pub struct PendingResume(pub Snapshot);
+
+/// Browsers don't reliably let us save on exit, so also save every so often.
+#[derive(Resource)]
+pub struct Autosave(pub Timer);
+
+type BallQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static BallType,
+        Option<&'static BallTarget>,
+        Option<&'static BallProgress>,
+        Option<&'static Fuse>,
+        &'static Transform,
+        &'static Velocity,
+    ),
+>;
+
+impl Snapshot {
+    pub fn take(game: &Game, tiers: &BallTiers, balls: &BallQuery) -> Snapshot {
+        Snapshot {
+            seed: game.seed,
+            mode: game.mode,
+            score: game.score,
+            strikes: game.strikes,
+            max_level: game.max_level,
+            max_combo: game.max_combo,
+            final_tiers_reached: game.final_tiers_reached,
+            final_tiers_cleared: game.final_tiers_cleared,
+            time_left: game.time_left,
+            bonus_seconds: game.bonus_seconds,
+            overflow_time: game.overflow_time,
+            tiers_fingerprint: Some(tiers.fingerprint()),
+            next_ball: game.dropper.next_ball.ball_type,
+            held: game.dropper.held,
+            hold_used: game.dropper.hold_used,
+            queue: game.dropper.queue.iter().copied().collect(),
+            bag: game.dropper.spawner.bag.clone(),
+            rng: game.dropper.rng.clone(),
+            balls: balls
+                .iter()
+                .map(
+                    |(ball_type, target, progress, fuse, transform, velocity)| BallSnapshot {
+                        ball_type: *ball_type,
+                        target: target.map(|target| target.0),
+                        progress: progress.map(|progress| progress.0),
+                        fuse: fuse.map(|fuse| fuse.0),
+                        position: transform.translation.truncate().to_array(),
+                        rotation: transform.rotation.to_euler(EulerRot::XYZ).2,
+                        linvel: velocity.linvel.to_array(),
+                        angvel: velocity.angvel,
+                    },
+                )
+                .collect(),
+        }
+    }
+
+    pub fn load() -> Option<Snapshot> {
+        ron::from_str(&storage::load(SAVE_STORAGE_NAME)?).ok()
+    }
+
+    pub fn save(&self) {
+        let result = ron::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| storage::save(SAVE_STORAGE_NAME, &contents));
+        if let Err(error) = result {
+            error!("Couldn't save the game: {error}");
+        }
+    }
+}
+
+pub fn resume_game_system(
+    mut commands: Commands,
+    pending: Res<PendingResume>,
+    mut game: ResMut<Game>,
+    tiers: Res<BallTiers>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let snapshot = &pending.0;
+    if snapshot
+        .tiers_fingerprint
+        .is_some_and(|fingerprint| fingerprint != tiers.fingerprint())
+    {
+        warn!("The saved game was played with different ball tiers, its balls will change size");
+    }
+    game.seed = snapshot.seed;
+    game.mode = snapshot.mode;
+    game.score = snapshot.score;
+    game.interpolated_score = snapshot.score;
+    game.strikes = snapshot.strikes;
+    game.max_level = snapshot.max_level;
+    game.max_combo = snapshot.max_combo;
+    game.final_tiers_reached = snapshot.final_tiers_reached;
+    game.final_tiers_cleared = snapshot.final_tiers_cleared;
+    game.time_left = snapshot.time_left;
+    game.resumed = true;
+    game.bonus_seconds = snapshot.bonus_seconds;
+    game.overflow_time = snapshot.overflow_time;
+    game.dropper.rng = snapshot.rng.clone();
+    game.dropper.spawner.bag = snapshot.bag.clone();
+    game.dropper.set_next(
+        snapshot.next_ball,
+        &tiers,
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+    );
+    game.dropper.set_held(
+        snapshot.held,
+        &tiers,
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+    );
+    game.dropper.hold_used = snapshot.hold_used;
+    game.dropper.set_queue(
+        snapshot.queue.iter().copied(),
+        &tiers,
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+    );
+    for ball in &snapshot.balls {
+        let [x, y] = ball.position;
+        let entity = spawn_ball(
+            &mut commands,
+            &tiers,
+            ball.ball_type,
+            ball.target.map(BallTarget),
+            Transform::from_xyz(x, y, 0.).with_rotation(Quat::from_rotation_z(ball.rotation)),
+            &mut meshes,
+            &mut materials,
+        );
+        commands.entity(entity).insert(Velocity {
+            linvel: Vec2::from_array(ball.linvel),
+            angvel: ball.angvel,
+        });
+        if let Some(progress) = ball.progress {
+            commands.entity(entity).insert(BallProgress(progress));
+        }
+        if let Some(fuse) = ball.fuse {
+            commands.entity(entity).insert(Fuse(fuse));
+        }
+    }
+    commands.remove_resource::<PendingResume>();
+}
+
+pub fn autosave_system(
+    time: Res<Time>,
+    mut autosave: ResMut<Autosave>,
+    game: Res<Game>,
+    tiers: Res<BallTiers>,
+    balls: BallQuery,
+) {
+    if autosave.0.tick(time.delta()).just_finished() {
+        Snapshot::take(&game, &tiers, &balls).save();
+    }
+}
+
+pub fn save_on_exit_system(
+    game: Res<Game>,
+    tiers: Res<BallTiers>,
+    balls: BallQuery,
+    state: Res<State<AppState>>,
+    exit_ev: EventReader<AppExit>,
+) {
+    if !exit_ev.is_empty() && matches!(state.get(), AppState::Playing | AppState::Paused) {
+        Snapshot::take(&game, &tiers, &balls).save();
+    }
+}
+
+/// Quitting to the menu keeps the game around to continue from there.
+pub fn save_on_quit_system(
+    mut commands: Commands,
+    game: Res<Game>,
+    tiers: Res<BallTiers>,
+    balls: BallQuery,
+) {
+    let snapshot = Snapshot::take(&game, &tiers, &balls);
+    snapshot.save();
+    commands.insert_resource(PendingResume(snapshot));
+}
+
+/// A finished game has nothing to come back to.
+pub fn clear_save_system() {
+    storage::remove(SAVE_STORAGE_NAME);
+}
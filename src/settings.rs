@@ -0,0 +1,56 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+
+const SETTINGS_STORAGE_NAME: &str = "settings";
+
+/// Player preferences, kept across sessions.
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Settings::load());
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Settings {
+    /// Pause when the window loses focus.
+    pub auto_pause: bool,
+    /// How fast the keyboard and gamepad move the aim, in world units per second.
+    pub aim_speed: f32,
+    /// How many of the queued balls after the next one are shown.
+    pub lookahead: usize,
+}
+
+/// The aim speeds the settings screen cycles through.
+pub const AIM_SPEEDS: [f32; 4] = [150., 300., 450., 600.];
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            auto_pause: true,
+            aim_speed: AIM_SPEEDS[1],
+            lookahead: 3,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Settings {
+        storage::load(SETTINGS_STORAGE_NAME)
+            .and_then(|contents| ron::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| e.to_string())
+            .and_then(|contents| storage::save(SETTINGS_STORAGE_NAME, &contents));
+        if let Err(error) = result {
+            error!("Couldn't save settings: {error}");
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! How the dropper picks the level of each simple ball it hands out.
+
+use bevy_turborand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::tiers::BallTiers;
+
+/// Strategy for picking the level of a freshly dropped simple ball, set by
+/// `GameRules::spawn_distribution`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub enum SpawnDistribution {
+    /// Every pick is independent, by the tiers' spawn weights.
+    #[default]
+    Weighted,
+    /// Deals from a shuffled bag holding each tier as many times as its spawn weight, and only
+    /// refills it once it's empty. Every tier with a weight comes up at least once in any run
+    /// of twice the bag's size.
+    Bag,
+    /// Picks evenly from the first few levels, with more of them on offer as the biggest ball
+    /// in the bucket grows: `below_top` levels under it, but never fewer than `min_levels` or
+    /// more than `max_levels`.
+    Adaptive {
+        min_levels: i32,
+        max_levels: i32,
+        below_top: i32,
+    },
+}
+
+/// The dropper's spawn distribution and whatever it needs to remember between picks.
+#[derive(Clone, Default)]
+pub struct Spawner {
+    pub distribution: SpawnDistribution,
+    /// What's left in the bag, dealt from the back.
+    pub bag: Vec<i32>,
+    /// Level of the biggest ball in the bucket, counting growing balls at the level they're
+    /// growing into.
+    pub top_level: i32,
+}
+
+impl Spawner {
+    pub fn new(distribution: SpawnDistribution) -> Spawner {
+        Spawner {
+            distribution,
+            ..Default::default()
+        }
+    }
+
+    /// Forgets the picks of the last game.
+    pub fn reset(&mut self, distribution: SpawnDistribution) {
+        *self = Spawner::new(distribution);
+    }
+
+    pub fn roll_level(&mut self, tiers: &BallTiers, rng: &mut RngComponent) -> i32 {
+        match self.distribution {
+            SpawnDistribution::Weighted => tiers.roll_level(rng),
+            SpawnDistribution::Bag => {
+                if self.bag.is_empty() {
+                    for (index, tier) in tiers.tiers.iter().enumerate() {
+                        let level = index as i32 + 1;
+                        self.bag
+                            .extend(std::iter::repeat_n(level, tier.spawn_weight as usize));
+                    }
+                    rng.shuffle(&mut self.bag);
+                }
+                self.bag.pop().expect("some tier always has a spawn weight")
+            }
+            SpawnDistribution::Adaptive {
+                min_levels,
+                max_levels,
+                below_top,
+            } => {
+                let levels = (self.top_level - below_top)
+                    .min(max_levels)
+                    .max(min_levels)
+                    .clamp(1, tiers.tiers.len() as i32);
+                rng.i32(1..=levels)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawned_levels(spawner: &mut Spawner, tiers: &BallTiers, count: usize) -> Vec<i32> {
+        let mut rng = RngComponent::with_seed(7);
+        (0..count)
+            .map(|_| spawner.roll_level(tiers, &mut rng))
+            .collect()
+    }
+
+    #[test]
+    fn bag_deals_every_weighted_tier_before_refilling() {
+        let tiers = BallTiers::default();
+        let bag_size = tiers
+            .tiers
+            .iter()
+            .map(|tier| tier.spawn_weight as usize)
+            .sum();
+        let mut spawner = Spawner::new(SpawnDistribution::Bag);
+        let levels = spawned_levels(&mut spawner, &tiers, bag_size);
+        assert!(spawner.bag.is_empty());
+        for (index, tier) in tiers.tiers.iter().enumerate() {
+            let level = index as i32 + 1;
+            let dealt = levels.iter().filter(|dealt| **dealt == level).count();
+            assert_eq!(dealt, tier.spawn_weight as usize, "level {level}");
+        }
+    }
+
+    #[test]
+    fn adaptive_range_follows_top_level() {
+        let tiers = BallTiers::default();
+        let mut spawner = Spawner::new(SpawnDistribution::Adaptive {
+            min_levels: 2,
+            max_levels: 5,
+            below_top: 2,
+        });
+        // Never fewer than `min_levels`, never more than `max_levels`, `below_top` under the
+        // top in between
+        for (top_level, levels) in [(0, 2), (3, 2), (5, 3), (6, 4), (7, 5), (11, 5)] {
+            spawner.top_level = top_level;
+            let spawned = spawned_levels(&mut spawner, &tiers, 200);
+            assert_eq!(spawned.iter().min(), Some(&1), "top level {top_level}");
+            assert_eq!(spawned.iter().max(), Some(&levels), "top level {top_level}");
+        }
+    }
+}
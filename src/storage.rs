@@ -0,0 +1,72 @@
+//! Small named blobs of persistent data: RON files under the user's data directory, or
+//! `localStorage` entries when running in a browser.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load(name: &str) -> Option<String> {
+    std::fs::read_to_string(path(name)?).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save(name: &str, contents: &str) -> Result<(), String> {
+    let path = path(name).ok_or("no data directory")?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(path, contents).map_err(|e| e.to_string())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn remove(name: &str) {
+    if let Some(path) = path(name) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn path(name: &str) -> Option<std::path::PathBuf> {
+    Some(dirs::data_dir()?.join("bingle").join(format!("{name}.ron")))
+}
+
+/// Seconds since the unix epoch.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load(name: &str) -> Option<String> {
+    local_storage()?.get_item(&key(name)).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save(name: &str, contents: &str) -> Result<(), String> {
+    local_storage()
+        .ok_or("no local storage")?
+        .set_item(&key(name), contents)
+        .map_err(|e| format!("{e:?}"))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn remove(name: &str) {
+    if let Some(storage) = local_storage() {
+        let _ = storage.remove_item(&key(name));
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+fn key(name: &str) -> String {
+    format!("bingle.{name}")
+}
+
+/// Seconds since the unix epoch.
+#[cfg(target_arch = "wasm32")]
+pub fn now() -> u64 {
+    (js_sys::Date::now() / 1000.) as u64
+}
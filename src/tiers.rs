@@ -0,0 +1,314 @@
+//! Ball tiers: the size, look, score and physics of each ball level, loaded from
+//! `assets/balls.tiers.ron`.
+//!
+//! The file is watched through the `AssetServer`, so with Bevy's `file_watcher` feature
+//! (`cargo run --features bevy/file_watcher`) edits show up in a running game.
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext, LoadState},
+    prelude::*,
+    sprite::Mesh2dHandle,
+    utils::BoxedFuture,
+};
+use bevy_turborand::prelude::*;
+use serde::{Deserialize, Deserializer};
+
+use crate::ball::{BallProgress, BallTarget, BallType};
+
+const BALL_TIERS_PATH: &str = "balls.tiers.ron";
+
+/// Loads the ball tiers and keeps [`BallTiers`] and the balls in play up to date with the file.
+pub struct BallTiersPlugin;
+
+impl Plugin for BallTiersPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<BallTiers>()
+            .init_asset_loader::<BallTiersLoader>()
+            // The copy built into the game covers the time until the file is loaded, and any
+            // setup without one
+            .insert_resource(BallTiers::default())
+            .add_systems(Startup, load_ball_tiers_system)
+            .add_systems(
+                Update,
+                (
+                    apply_ball_tiers_system,
+                    refresh_balls_system
+                        .after(apply_ball_tiers_system)
+                        .run_if(resource_changed::<BallTiers>()),
+                ),
+            );
+    }
+}
+
+/// Every ball tier, smallest first. `BallType::Simple(level)` is the tier at `level - 1`.
+#[derive(Asset, Resource, TypePath, Deserialize, Clone)]
+pub struct BallTiers {
+    pub tiers: Vec<BallTier>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BallTier {
+    pub radius: f32,
+    #[serde(deserialize_with = "deserialize_hex_color")]
+    pub color: Color,
+    /// Image drawn on the ball, tinted by `color`.
+    #[serde(default, rename = "texture")]
+    pub texture_path: Option<String>,
+    #[serde(skip)]
+    pub texture: Option<Handle<Image>>,
+    /// Points each ball of the tier is worth when it merges.
+    pub score: i32,
+    #[serde(default = "default_density")]
+    pub density: f32,
+    #[serde(default = "default_restitution")]
+    pub restitution: f32,
+    #[serde(default)]
+    pub friction: f32,
+    /// How often the dropper hands this tier out, relative to the others. Never when 0.
+    #[serde(default)]
+    pub spawn_weight: u32,
+    /// Balls of the final tier merge no further. Two of them clear each other out instead.
+    #[serde(default)]
+    pub final_tier: bool,
+}
+
+pub fn default_density() -> f32 {
+    1.
+}
+
+pub fn default_restitution() -> f32 {
+    0.2
+}
+
+fn deserialize_hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let hex = String::deserialize(deserializer)?;
+    Color::hex(&hex).map_err(serde::de::Error::custom)
+}
+
+impl Default for BallTiers {
+    fn default() -> Self {
+        let tiers: BallTiers = ron::from_str(include_str!("../assets/balls.tiers.ron"))
+            .expect("the built in ball tiers should parse");
+        tiers
+            .validate()
+            .expect("the built in ball tiers should be valid");
+        tiers
+    }
+}
+
+impl BallTiers {
+    /// The tier of a simple ball, with levels past either end clamped to the first or last.
+    pub fn tier(&self, level: i32) -> &BallTier {
+        let index = (level - 1).clamp(0, self.tiers.len() as i32 - 1);
+        &self.tiers[index as usize]
+    }
+
+    /// Whether balls of this level can't merge into a bigger one.
+    pub fn is_final(&self, level: i32) -> bool {
+        level as usize >= self.tiers.len() || self.tier(level).final_tier
+    }
+
+    /// Picks the level of a freshly dropped ball by the tiers' spawn weights.
+    pub fn roll_level(&self, rng: &mut RngComponent) -> i32 {
+        let total = self.tiers.iter().map(|tier| tier.spawn_weight).sum::<u32>();
+        let mut pick = rng.u32(0..total);
+        for (index, tier) in self.tiers.iter().enumerate() {
+            if pick < tier.spawn_weight {
+                return index as i32 + 1;
+            }
+            pick -= tier.spawn_weight;
+        }
+        unreachable!("the pick is always below the total weight")
+    }
+
+    /// A hash of everything in the tiers that changes how a game plays out, to tell whether a
+    /// replay or save was made with the same ones.
+    pub fn fingerprint(&self) -> u64 {
+        // FNV-1a, which unlike `DefaultHasher` stays the same from one build to the next
+        fn write(hash: &mut u64, bytes: &[u8]) {
+            for byte in bytes {
+                *hash ^= *byte as u64;
+                *hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+        let mut hash = 0xcbf29ce484222325;
+        for tier in &self.tiers {
+            let floats = [
+                tier.radius,
+                tier.density,
+                tier.restitution,
+                tier.friction,
+                // Tiers of the same colour merge in `GameMode::Colors`
+                tier.color.r(),
+                tier.color.g(),
+                tier.color.b(),
+            ];
+            for float in floats {
+                write(&mut hash, &float.to_bits().to_le_bytes());
+            }
+            write(&mut hash, &tier.score.to_le_bytes());
+            write(&mut hash, &tier.spawn_weight.to_le_bytes());
+            write(&mut hash, &[tier.final_tier as u8]);
+        }
+        hash
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.tiers.is_empty() {
+            return Err("there are no tiers".into());
+        }
+        if let Some(index) = self.tiers.iter().position(|tier| tier.radius <= 0.) {
+            return Err(format!("tier {} has no size", index + 1));
+        }
+        if self.tiers.iter().all(|tier| tier.spawn_weight == 0) {
+            return Err("no tier has a spawn weight".into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum BallTiersError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Invalid(String),
+}
+
+impl std::fmt::Display for BallTiersError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "couldn't read ball tiers: {error}"),
+            Self::Parse(error) => write!(f, "couldn't parse ball tiers: {error}"),
+            Self::Invalid(error) => write!(f, "invalid ball tiers: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for BallTiersError {}
+
+#[derive(Default)]
+pub struct BallTiersLoader;
+
+impl AssetLoader for BallTiersLoader {
+    type Asset = BallTiers;
+    type Settings = ();
+    type Error = BallTiersError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<BallTiers, BallTiersError>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader
+                .read_to_end(&mut bytes)
+                .await
+                .map_err(BallTiersError::Io)?;
+            let mut tiers: BallTiers =
+                ron::de::from_bytes(&bytes).map_err(BallTiersError::Parse)?;
+            tiers.validate().map_err(BallTiersError::Invalid)?;
+            for tier in &mut tiers.tiers {
+                tier.texture = tier
+                    .texture_path
+                    .clone()
+                    .map(|path| load_context.load(path));
+            }
+            Ok(tiers)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tiers.ron"]
+    }
+}
+
+#[derive(Resource)]
+pub struct BallTiersHandle {
+    pub handle: Handle<BallTiers>,
+    /// Whether the file's tiers have replaced the built in ones yet.
+    pub applied: bool,
+}
+
+pub fn load_ball_tiers_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(BallTiersHandle {
+        handle: asset_server.load(BALL_TIERS_PATH),
+        applied: false,
+    });
+}
+
+/// Takes on the tiers from the file once it's loaded, and again whenever it changes.
+pub fn apply_ball_tiers_system(
+    mut tiers_ev: EventReader<AssetEvent<BallTiers>>,
+    assets: Res<Assets<BallTiers>>,
+    mut handle: ResMut<BallTiersHandle>,
+    mut tiers: ResMut<BallTiers>,
+) {
+    for event in tiers_ev.read() {
+        if let AssetEvent::Added { id } | AssetEvent::Modified { id } = event {
+            if *id != handle.handle.id() {
+                continue;
+            }
+            if let Some(loaded) = assets.get(*id) {
+                *tiers = loaded.clone();
+                handle.applied = true;
+                info!("Loaded {} ball tiers", tiers.tiers.len());
+            }
+        }
+    }
+}
+
+/// Whether the tiers file has been taken on, or failed to load so the built in tiers stay.
+/// Games wait for it so the same seed always plays out with the same tiers.
+pub fn ball_tiers_ready(
+    handle: Option<Res<BallTiersHandle>>,
+    asset_server: Res<AssetServer>,
+) -> bool {
+    handle.is_some_and(|handle| {
+        handle.applied
+            || matches!(
+                asset_server.get_load_state(&handle.handle),
+                Some(LoadState::Failed) | None
+            )
+    })
+}
+
+/// Brings the balls in play in line with changed tiers. Growing balls catch up once they're
+/// done.
+#[allow(clippy::type_complexity)]
+pub fn refresh_balls_system(
+    mut commands: Commands,
+    tiers: Res<BallTiers>,
+    balls: Query<
+        (Entity, &BallType, &Mesh2dHandle, &Handle<ColorMaterial>),
+        (Without<BallTarget>, Without<BallProgress>),
+    >,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (entity, ball_type, mesh, material) in balls.iter() {
+        meshes.insert(&mesh.0, shape::Circle::new(ball_type.size(&tiers)).into());
+        materials.insert(material, ball_type.color(&tiers));
+        commands.entity(entity).insert(ball_type.physics(&tiers));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_follows_gameplay_changes() {
+        let tiers = BallTiers::default();
+        assert_eq!(tiers.fingerprint(), BallTiers::default().fingerprint());
+
+        let mut resized = tiers.clone();
+        resized.tiers[0].radius += 1.;
+        assert_ne!(tiers.fingerprint(), resized.fingerprint());
+
+        let mut retextured = tiers.clone();
+        retextured.tiers[0].texture_path = Some("balls/other.png".into());
+        assert_eq!(tiers.fingerprint(), retextured.fingerprint());
+    }
+}